@@ -1,54 +1,198 @@
 mod parse;
 
 use proc_macro::TokenStream as TokenStream1;
-use quote::quote_spanned;
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned, ToTokens};
 use std::path::Path;
 use syn::parse::{Parse, ParseStream};
 use syn::parse_macro_input;
+use syn::punctuated::Punctuated;
 
 struct Input {
-    crate_path: syn::Ident,
-    file: syn::LitStr,
+    files: Vec<syn::LitStr>,
+    dispatch: Option<syn::Ident>,
+    invocation: parse::Invocation,
 }
 
 impl Parse for Input {
     fn parse(input: ParseStream) -> syn::parse::Result<Self> {
-        Ok(Self {
-            crate_path: input.parse()?,
-            file: input.parse()?,
-        })
+        let mut result = Self {
+            files: Vec::new(),
+            dispatch: None,
+            invocation: parse::Invocation::new(input.parse()?),
+        };
+        while !input.is_empty() {
+            if input.peek(syn::LitStr) {
+                result.files.push(input.parse()?);
+            } else {
+                let option: syn::Ident = input.parse()?;
+                match option.to_string().as_str() {
+                    "dispatch" => {
+                        input.parse::<syn::Token![=]>()?;
+                        result.dispatch = Some(input.parse()?);
+                    }
+                    "modules" => result.invocation.modules = true,
+                    "deterministic" => result.invocation.types.deterministic = true,
+                    "module" => {
+                        input.parse::<syn::Token![=]>()?;
+                        result.invocation.module = Some(input.parse()?);
+                    }
+                    "vis" => {
+                        input.parse::<syn::Token![=]>()?;
+                        let vis: syn::Visibility = input.parse()?;
+                        result.invocation.vis = vis.into_token_stream();
+                    }
+                    "derive" => {
+                        let content;
+                        syn::parenthesized!(content in input);
+                        let paths =
+                            content.parse_terminated::<_, syn::Token![,]>(syn::Path::parse)?;
+                        for path in paths {
+                            let name = path.segments.last().unwrap().ident.to_string();
+                            result
+                                .invocation
+                                .derives
+                                .push((name, path.into_token_stream()));
+                        }
+                    }
+                    "attrs" => {
+                        let content;
+                        syn::parenthesized!(content in input);
+                        while !content.is_empty() {
+                            let name: syn::Ident = content.parse()?;
+                            content.parse::<syn::Token![=]>()?;
+                            let attrs = content.call(syn::Attribute::parse_outer)?;
+                            let mut derives = Vec::new();
+                            for attr in attrs.iter().filter(|attr| attr.path.is_ident("derive")) {
+                                let paths = attr.parse_args_with(
+                                    Punctuated::<syn::Path, syn::Token![,]>::parse_terminated,
+                                )?;
+                                for path in paths {
+                                    derives.push(path.segments.last().unwrap().ident.to_string());
+                                }
+                            }
+                            let attrs = quote!(#(#attrs)*);
+                            result
+                                .invocation
+                                .attrs
+                                .insert(name.to_string(), (derives, attrs));
+                            if !content.is_empty() {
+                                content.parse::<syn::Token![,]>()?;
+                            }
+                        }
+                    }
+                    "types" => {
+                        let content;
+                        syn::parenthesized!(content in input);
+                        let types = parse_assignments(&content, |c| c.parse::<syn::Ident>())?;
+                        for (name, ty) in types {
+                            let slot = match name.to_string().as_str() {
+                                "string" => &mut result.invocation.types.string,
+                                "bytes" => &mut result.invocation.types.bytes,
+                                "array" => &mut result.invocation.types.array,
+                                "map" => &mut result.invocation.types.map,
+                                _ => return Err(syn::Error::new(name.span(), "unknown type")),
+                            };
+                            *slot = Some(ty);
+                        }
+                    }
+                    "fields" => {
+                        let content;
+                        syn::parenthesized!(content in input);
+                        let fields = parse_assignments(&content, |c| {
+                            let definition: syn::Ident = c.parse()?;
+                            c.parse::<syn::Token![.]>()?;
+                            let field: syn::Ident = c.parse()?;
+                            Ok(format!("{}.{}", definition, field))
+                        })?;
+                        result.invocation.types.fields.extend(fields);
+                    }
+                    _ => return Err(syn::Error::new(option.span(), "unknown option")),
+                }
+            }
+            if !input.is_empty() {
+                input.parse::<syn::Token![,]>()?;
+            }
+        }
+        Ok(result)
     }
 }
 
+/// Parse a comma separated list of `key = Type`.
+fn parse_assignments<K>(
+    input: ParseStream,
+    parse_key: impl Fn(ParseStream) -> syn::parse::Result<K>,
+) -> syn::parse::Result<Vec<(K, TokenStream)>> {
+    let mut result = Vec::new();
+    while !input.is_empty() {
+        let key = parse_key(input)?;
+        input.parse::<syn::Token![=]>()?;
+        let ty: syn::Type = input.parse()?;
+        result.push((key, ty.into_token_stream()));
+        if !input.is_empty() {
+            input.parse::<syn::Token![,]>()?;
+        }
+    }
+    Ok(result)
+}
+
 #[doc(hidden)]
 #[proc_macro]
 pub fn read_bebop(input: TokenStream1) -> TokenStream1 {
-    let input = parse_macro_input!(input as Input);
+    let mut input = parse_macro_input!(input as Input);
+
+    let mut rust = TokenStream::new();
 
-    let crate_path = input.crate_path;
+    loop {
+        for input_file in &input.files {
+            let file = if let Some(root) = std::env::var_os("CARGO_MANIFEST_DIR") {
+                Path::new(&root).join(input_file.value())
+            } else {
+                input_file.value().into()
+            };
 
-    let file = if let Some(root) = std::env::var_os("CARGO_MANIFEST_DIR") {
-        Path::new(&root).join(&input.file.value())
-    } else {
-        input.file.value().into()
-    };
+            let src = match std::fs::read_to_string(&file) {
+                Ok(src) => src,
+                Err(e) => {
+                    let msg = format!("unable to open {:?}: {}", file, e);
+                    return quote_spanned!(input_file.span() => compile_error! { #msg }).into();
+                }
+            };
 
-    let src = match std::fs::read_to_string(&file) {
-        Ok(src) => src,
-        Err(e) => {
-            let msg = format!("unable to open {:?}: {}", file, e);
-            return quote_spanned!(input.file.span() => compile_error! { #msg }).into();
+            match input.invocation.parse_file(&file, &src) {
+                Ok(tokens) => rust.extend(tokens),
+                Err(e) => return e.into(),
+            }
+        }
+        if !input.invocation.next_pass() {
+            break;
         }
-    };
+        rust = TokenStream::new();
+    }
+
+    rust.extend(input.invocation.module_defs.clone());
+    if let Some(name) = &input.dispatch {
+        rust.extend(input.invocation.dispatch_enum(name));
+    }
 
-    let mut parser = parse::Parser {
-        file: &file,
-        crate_path,
-        src: &src,
-    };
+    let invocation = &input.invocation;
+    if let Err(e) = invocation.types.check_fields() {
+        return e.into();
+    }
+    if let Err(e) = invocation.check_attrs() {
+        return e.into();
+    }
 
-    match parser.parse_file() {
-        Ok(tokens) => tokens.into(),
-        Err(e) => e.into(),
+    if let Some(module) = &invocation.module {
+        let vis = &invocation.vis;
+        rust = quote!(
+            #vis mod #module {
+                #[allow(unused_imports)]
+                use super::*;
+                #rust
+            }
+        );
     }
+
+    rust.into()
 }
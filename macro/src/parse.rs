@@ -1,14 +1,9 @@
-use proc_macro2::{Ident, Span, TokenStream};
-use quote::quote;
+use proc_macro2::{Ident, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote};
 use std::cmp::max;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use std::path::Path;
-
-pub struct Parser<'a> {
-    pub src: &'a str,
-    pub file: &'a Path,
-    pub crate_path: Ident,
-}
+use std::path::{Path, PathBuf};
 
 macro_rules! error {
     ($($t:tt)*) => {
@@ -19,15 +14,659 @@ macro_rules! error {
     }
 }
 
+/// The options and state shared by all files of one `bebop!` invocation.
+pub struct Invocation {
+    pub crate_path: Ident,
+    pub types: TypeMap,
+    /// The visibility of generated types and fields.
+    pub vis: TokenStream,
+    /// Traits to derive for all generated types, by name and path.
+    pub derives: Vec<(String, TokenStream)>,
+    /// Extra attributes of generated types and the traits they derive, by name.
+    pub attrs: HashMap<String, (Vec<String>, TokenStream)>,
+    /// The keys of `attrs` that name a generated type.
+    pub used_attrs: HashSet<String>,
+    /// Put all generated code in a module with this name.
+    pub module: Option<Ident>,
+    /// The traits of all definitions seen so far, by path.
+    pub traits: HashMap<String, Traits>,
+    /// The traits of all definitions seen by the previous pass, by path.
+    pub previous_traits: HashMap<String, Traits>,
+    /// Put the definitions of each file in a module named after the file.
+    pub modules: bool,
+    /// All definitions with an opcode seen so far, with the name of their
+    /// dispatch enum variant and their path.
+    pub opcodes: Vec<(u32, Ident, TokenStream)>,
+    /// All files parsed so far.
+    pub imported: Vec<PathBuf>,
+    /// The files currently being parsed, each imported by the one before.
+    pub import_stack: Vec<PathBuf>,
+    /// The modules generated so far, if `modules` is set.
+    pub module_defs: TokenStream,
+    /// The modules imported by each module generated so far, directly or not.
+    pub module_imports: HashMap<String, Vec<Ident>>,
+    /// What each type name generated so far is used for, by its path.
+    pub type_names: HashMap<String, String>,
+}
+
+impl Invocation {
+    pub fn new(crate_path: Ident) -> Self {
+        Self {
+            crate_path,
+            types: TypeMap::default(),
+            vis: quote!(pub),
+            derives: Vec::new(),
+            attrs: HashMap::new(),
+            used_attrs: HashSet::new(),
+            module: None,
+            traits: HashMap::new(),
+            previous_traits: HashMap::new(),
+            modules: false,
+            opcodes: Vec::new(),
+            imported: Vec::new(),
+            import_stack: Vec::new(),
+            module_defs: TokenStream::new(),
+            module_imports: HashMap::new(),
+            type_names: HashMap::new(),
+        }
+    }
+
+    /// Parse `src`, the contents of `file`, unless that file was parsed before.
+    ///
+    /// Returns the definitions, or nothing if they were put in a module.
+    pub fn parse_file(&mut self, file: &Path, src: &str) -> Result<TokenStream, TokenStream> {
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.into());
+        if let Some(i) = self.import_stack.iter().position(|f| *f == canonical) {
+            let chain = self.import_stack[i..]
+                .iter()
+                .chain(Some(&canonical))
+                .map(|f| f.display().to_string())
+                .collect::<Vec<_>>();
+            return error!("circular import: {}", chain.join(" -> "));
+        }
+        if self.imported.contains(&canonical) {
+            return Ok(TokenStream::new());
+        }
+        self.imported.push(canonical.clone());
+        self.import_stack.push(canonical);
+        let module = if self.modules {
+            let module = module_name(file)?;
+            let purpose = format!("the module of {}", file.display());
+            self.declare_type(None, &module, purpose)?;
+            Some(module)
+        } else {
+            None
+        };
+        let mut parser = Parser {
+            src,
+            file,
+            module: module.clone(),
+            imports: Vec::new(),
+            docs: Vec::new(),
+            invocation: self,
+        };
+        let rust = parser.parse_file();
+        let imports = std::mem::take(&mut parser.imports);
+        self.import_stack.pop();
+        let rust = rust?;
+        match module {
+            Some(module) => {
+                self.module_imports.insert(module.to_string(), imports);
+                let vis = &self.vis;
+                self.module_defs.extend(quote!(
+                    #vis mod #module {
+                        #[allow(unused_imports)]
+                        use super::*;
+                        #rust
+                    }
+                ));
+                Ok(TokenStream::new())
+            }
+            None => Ok(rust),
+        }
+    }
+
+    /// Reserve type name `name` in `module` for `purpose`.
+    fn declare_type(
+        &mut self,
+        module: Option<&Ident>,
+        name: &Ident,
+        purpose: String,
+    ) -> Result<(), TokenStream> {
+        let path = type_path(module, name);
+        match self.type_names.get(&path) {
+            Some(other) if *other == purpose => error!("`{}` is defined more than once", name),
+            Some(other) => error!("`{}` is used for both {} and {}", name, other, purpose),
+            None => {
+                self.type_names.insert(path, purpose);
+                Ok(())
+            }
+        }
+    }
+
+    /// Start parsing all files again, if the traits of any definition
+    /// changed since the previous pass.
+    ///
+    /// A definition can refer to definitions that come later, whose traits
+    /// are only known from the previous pass. Once every definition has been
+    /// seen, each pass can only add traits, so this ends once all references
+    /// see the final traits.
+    pub fn next_pass(&mut self) -> bool {
+        if self.traits == self.previous_traits {
+            return false;
+        }
+        self.previous_traits = std::mem::take(&mut self.traits);
+        self.opcodes.clear();
+        self.imported.clear();
+        self.import_stack.clear();
+        self.module_defs = TokenStream::new();
+        self.module_imports.clear();
+        self.type_names.clear();
+        self.types.used_fields.clear();
+        self.used_attrs.clear();
+        true
+    }
+
+    /// Record the traits of definition `name` in `module`.
+    fn add_traits(&mut self, module: Option<&Ident>, name: &Ident, traits: Traits) {
+        self.traits.insert(type_path(module, name), traits);
+    }
+
+    /// The attributes of generated type `name`, which always derives `derives`.
+    ///
+    /// Traits that `attrs` already derives for `name` are left out.
+    fn type_attrs(&mut self, name: &Ident, derives: &[&str]) -> TokenStream {
+        let (user, attrs) = match self.attrs.get(&name.to_string()) {
+            Some((user, attrs)) => {
+                self.used_attrs.insert(name.to_string());
+                (user.as_slice(), Some(attrs))
+            }
+            None => (&[][..], None),
+        };
+        let extra = self
+            .derives
+            .iter()
+            .filter(|(derive, _)| !derives.contains(&derive.as_str()) && !user.contains(derive))
+            .map(|(_, path)| path);
+        let derives = derives
+            .iter()
+            .filter(|derive| !user.iter().any(|u| u == *derive))
+            .map(|derive| Ident::new(derive, Span::call_site()));
+        quote!(
+            #[derive(#(#derives,)* #(#extra),*)]
+            #[allow(deprecated)]
+            #attrs
+        )
+    }
+
+    /// Fail if `attrs` names a type that is not generated.
+    pub fn check_attrs(&self) -> Result<(), TokenStream> {
+        let mut unused = self
+            .attrs
+            .keys()
+            .filter(|key| !self.used_attrs.contains(*key))
+            .collect::<Vec<_>>();
+        unused.sort();
+        match unused.first() {
+            Some(key) => error!("`attrs` names `{}`, which is not a generated type", key),
+            None => Ok(()),
+        }
+    }
+
+    /// An enum of all definitions with an opcode, implementing `Dispatch`.
+    pub fn dispatch_enum(&mut self, name: &Ident) -> TokenStream {
+        let attrs = self.type_attrs(name, &["Clone", "Debug", "PartialEq"]);
+        let bebop = &self.crate_path;
+        let vis = &self.vis;
+        let opcodes = &self.opcodes;
+        let values = opcodes
+            .iter()
+            .map(|(opcode, _, _)| opcode)
+            .collect::<Vec<_>>();
+        let names = opcodes.iter().map(|(_, name, _)| name).collect::<Vec<_>>();
+        let paths = opcodes.iter().map(|(_, _, path)| path).collect::<Vec<_>>();
+        quote!(
+            #attrs
+            #vis enum #name {
+                #(#names(#paths),)*
+            }
+            #(
+                #[allow(deprecated)]
+                impl From<#paths> for #name {
+                    fn from(value: #paths) -> Self {
+                        Self::#names(value)
+                    }
+                }
+            )*
+            #[allow(deprecated)]
+            impl #bebop::Dispatch for #name {
+                fn read_from<R: std::io::Read>(opcode: u32, reader: &mut #bebop::Reader<R>) -> Result<Self, #bebop::Error> {
+                    match opcode {
+                        #(#values => Ok(Self::#names(reader.read()?)),)*
+                        _ => Err(reader.error(#bebop::ErrorKind::UnknownOpcode)),
+                    }
+                }
+                fn write_into<W: std::io::Write>(&self, writer: &mut #bebop::Writer<W>) -> Result<(), #bebop::Error> {
+                    match *self {
+                        #(Self::#names(ref v) => writer.write(v),)*
+                    }
+                }
+                fn encoded_size(&self) -> usize {
+                    match *self {
+                        #(Self::#names(ref v) => #bebop::Bebop::encoded_size(v),)*
+                    }
+                }
+                fn opcode(&self) -> u32 {
+                    match *self {
+                        #(Self::#names(_) => #values,)*
+                    }
+                }
+            }
+        )
+    }
+}
+
+/// The path of type `name` in `module`, relative to the generated code.
+fn type_path(module: Option<&Ident>, name: &Ident) -> String {
+    match module {
+        Some(module) => format!("{}::{}", module, name),
+        None => name.to_string(),
+    }
+}
+
+/// The name of the module for the definitions in `file`.
+fn module_name(file: &Path) -> Result<Ident, TokenStream> {
+    let name = file
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .replace(|c: char| !c.is_alphanumeric() && c != '_', "_");
+    if !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        return error!("{:?} cannot be used as a module name", name);
+    }
+    Ok(Ident::new(&name, Span::call_site()))
+}
+
+pub struct Parser<'a> {
+    pub src: &'a str,
+    pub file: &'a Path,
+    /// The module the definitions of this file are put in, if any.
+    pub module: Option<Ident>,
+    /// The modules of the files this file imports, directly or not.
+    pub imports: Vec<Ident>,
+    /// Doc comments skipped since the last token.
+    pub docs: Vec<String>,
+    pub invocation: &'a mut Invocation,
+}
+
+/// Which of the traits that are not derived for every type can be derived
+/// for a type.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Traits {
+    pub eq: bool,
+    pub hash: bool,
+    pub partial_ord: bool,
+    pub ord: bool,
+    pub default: bool,
+    /// The size of the type, if it is `Copy`.
+    pub copy_size: Option<usize>,
+}
+
+impl Traits {
+    /// Structs larger than this are not `Copy`, even if they could be.
+    const MAX_COPY_SIZE: usize = 32;
+
+    const fn all(size: usize) -> Self {
+        Self {
+            eq: true,
+            hash: true,
+            partial_ord: true,
+            ord: true,
+            default: true,
+            copy_size: Some(size),
+        }
+    }
+
+    const fn float(size: usize) -> Self {
+        Self {
+            eq: false,
+            hash: false,
+            partial_ord: true,
+            ord: false,
+            default: true,
+            copy_size: Some(size),
+        }
+    }
+
+    /// The traits of a type containing both a `self` and an `other`.
+    fn and(self, other: Self) -> Self {
+        Self {
+            eq: self.eq && other.eq,
+            hash: self.hash && other.hash,
+            partial_ord: self.partial_ord && other.partial_ord,
+            ord: self.ord && other.ord,
+            default: self.default && other.default,
+            copy_size: match (self.copy_size, other.copy_size) {
+                (Some(a), Some(b)) => Some(a + b),
+                _ => None,
+            },
+        }
+    }
+
+    fn without_copy(self) -> Self {
+        Self {
+            copy_size: None,
+            ..self
+        }
+    }
+
+    /// The traits of a struct with fields of these traits.
+    fn of_struct(self) -> Self {
+        Self {
+            copy_size: self.copy_size.filter(|&size| size <= Self::MAX_COPY_SIZE),
+            ..self
+        }
+    }
+
+    /// `base` plus the traits that can be derived.
+    fn derives(self, base: &[&'static str]) -> Vec<&'static str> {
+        let mut derives = Vec::new();
+        if self.copy_size.is_some() {
+            derives.push("Copy");
+        }
+        derives.extend(base);
+        if self.default {
+            derives.push("Default");
+        }
+        if self.eq {
+            derives.push("Eq");
+        }
+        if self.partial_ord {
+            derives.push("PartialOrd");
+        }
+        if self.eq && self.partial_ord && self.ord {
+            derives.push("Ord");
+        }
+        if self.eq && self.hash {
+            derives.push("Hash");
+        }
+        derives
+    }
+}
+
+/// Rust types to use instead of the default ones.
+///
+/// `array` and `map` are templates in which each `_` is replaced by an
+/// element, key or value type, e.g. `smallvec::SmallVec<[_; 4]>`.
+#[derive(Default)]
+pub struct TypeMap {
+    pub string: Option<TokenStream>,
+    /// For `byte[]` and `array[byte]`.
+    pub bytes: Option<TokenStream>,
+    pub array: Option<TokenStream>,
+    pub map: Option<TokenStream>,
+    /// Use `BTreeMap` rather than `HashMap` when `map` is not set, so that
+    /// equal maps always have the same encoding.
+    pub deterministic: bool,
+    /// Types of single fields, by `Definition.field`.
+    pub fields: HashMap<String, TokenStream>,
+    /// The keys of `fields` that were found in the schema.
+    pub used_fields: HashSet<String>,
+}
+
+// Nothing is known about the traits of other types, so overrides get
+// `Traits::default()` rather than the traits of the type they replace.
+impl TypeMap {
+    fn string(&self) -> (TokenStream, Traits) {
+        match &self.string {
+            Some(t) => (t.clone(), Traits::default()),
+            None => (quote!(String), Traits::all(0).without_copy()),
+        }
+    }
+
+    /// An array of `element`, which has `traits`.
+    fn array(&self, element: TokenStream, byte: bool, traits: Traits) -> (TokenStream, Traits) {
+        match (&self.bytes, &self.array) {
+            (Some(bytes), _) if byte => (bytes.clone(), Traits::default()),
+            (_, Some(template)) => {
+                let t = fill_template(template.clone(), &mut vec![element].into_iter());
+                (t, Traits::default())
+            }
+            (_, None) => {
+                let traits = Traits {
+                    default: true,
+                    ..traits.without_copy()
+                };
+                (quote!(Vec<#element>), traits)
+            }
+        }
+    }
+
+    /// A map from `key` to `value`, which have `key_traits` and `value_traits`.
+    fn map(
+        &self,
+        key: TokenStream,
+        value: TokenStream,
+        key_traits: Traits,
+        value_traits: Traits,
+    ) -> (TokenStream, Traits) {
+        let traits = Traits {
+            eq: key_traits.eq && value_traits.eq,
+            default: true,
+            ..Traits::default()
+        };
+        match &self.map {
+            Some(template) => {
+                let t = fill_template(template.clone(), &mut vec![key, value].into_iter());
+                (t, Traits::default())
+            }
+            None if self.deterministic => {
+                (quote!(std::collections::BTreeMap<#key, #value>), traits)
+            }
+            None => (quote!(std::collections::HashMap<#key, #value>), traits),
+        }
+    }
+
+    fn field(&mut self, definition: &Ident, field: &Ident) -> Option<&TokenStream> {
+        let key = format!("{}.{}", definition, field);
+        let t = self.fields.get(&key)?;
+        self.used_fields.insert(key);
+        Some(t)
+    }
+
+    /// Fail if `fields` names a field that is not in the schema.
+    pub fn check_fields(&self) -> Result<(), TokenStream> {
+        let mut unused = self
+            .fields
+            .keys()
+            .filter(|key| !self.used_fields.contains(*key))
+            .collect::<Vec<_>>();
+        unused.sort();
+        match unused.first() {
+            Some(key) => error!("`fields` names `{}`, which is not a field", key),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Replace each `_` in `template` with the next type from `types`.
+fn fill_template(
+    template: TokenStream,
+    types: &mut impl Iterator<Item = TokenStream>,
+) -> TokenStream {
+    template
+        .into_iter()
+        .map(|tree| match tree {
+            TokenTree::Ident(ident) if ident == "_" => types.next().unwrap_or_else(|| quote!(_)),
+            TokenTree::Group(group) => {
+                let stream = fill_template(group.stream(), types);
+                let mut new = proc_macro2::Group::new(group.delimiter(), stream);
+                new.set_span(group.span());
+                TokenTree::Group(new).into()
+            }
+            tree => tree.into(),
+        })
+        .collect()
+}
+
+/// An attribute of a definition or member, e.g. `[opcode(0x1)]`.
+pub struct Attribute {
+    name: String,
+    args: Vec<AttributeValue>,
+}
+
+pub enum AttributeValue {
+    Number(i64),
+    String(String),
+    Ident(String),
+}
+
+/// The attributes of one definition or member.
+pub struct Attributes(Vec<Attribute>);
+
+impl Attributes {
+    const BUILT_IN: &'static [&'static str] = &["opcode", "deprecated", "default"];
+
+    /// Remove the attribute called `name`, if there is one.
+    fn take(&mut self, name: &str) -> Result<Option<Attribute>, TokenStream> {
+        let mut found = self.0.iter().filter(|attribute| attribute.name == name);
+        if found.nth(1).is_some() {
+            return error!("duplicate `{}` attribute", name);
+        }
+        Ok(self
+            .0
+            .iter()
+            .position(|attribute| attribute.name == name)
+            .map(|i| self.0.remove(i)))
+    }
+
+    pub fn opcode(&mut self) -> Result<Option<u32>, TokenStream> {
+        let args = match self.take("opcode")? {
+            None => return Ok(None),
+            Some(attribute) => attribute.args,
+        };
+        match args.as_slice() {
+            [AttributeValue::Number(opcode)] => match u32::try_from(*opcode) {
+                Ok(opcode) => Ok(Some(opcode)),
+                Err(_) => error!("opcodes must fit in a `uint32`, but got {}", opcode),
+            },
+            [AttributeValue::String(s)] => match <[u8; 4]>::try_from(s.as_bytes()) {
+                Ok(bytes) => Ok(Some(u32::from_le_bytes(bytes))),
+                Err(_) => error!("opcodes must be four bytes"),
+            },
+            [] => error!("`opcode` needs a value"),
+            _ => error!("`opcode` takes a single number or string"),
+        }
+    }
+
+    /// The Rust `#[deprecated]` attribute, if there is a `[deprecated]`.
+    pub fn deprecated(&mut self) -> Result<Option<TokenStream>, TokenStream> {
+        let args = match self.take("deprecated")? {
+            None => return Ok(None),
+            Some(attribute) => attribute.args,
+        };
+        match args.as_slice() {
+            [] => Ok(Some(quote!(#[deprecated]))),
+            [AttributeValue::String(message)] => Ok(Some(quote!(#[deprecated = #message]))),
+            _ => error!("`deprecated` takes at most one string"),
+        }
+    }
+
+    /// Whether there is a `[name]` attribute without arguments.
+    pub fn flag(&mut self, name: &str) -> Result<bool, TokenStream> {
+        match self.take(name)? {
+            None => Ok(false),
+            Some(Attribute { args, .. }) if args.is_empty() => Ok(true),
+            Some(_) => error!("`{}` does not take arguments", name),
+        }
+    }
+
+    /// The user-defined attributes, after all built-in ones that apply
+    /// have been taken.
+    pub fn custom(self) -> Result<Vec<Attribute>, TokenStream> {
+        let is_built_in = |a: &&Attribute| Self::BUILT_IN.contains(&a.name.as_str());
+        match self.0.iter().find(is_built_in) {
+            Some(attribute) => error!("`{}` cannot be used here", attribute.name),
+            None => Ok(self.0),
+        }
+    }
+}
+
+/// An implementation of `Attributes` listing the user-defined attributes of
+/// a definition and of its members.
+fn attributes_impl(
+    bebop: &Ident,
+    name: &Ident,
+    attributes: &[Attribute],
+    members: &[(String, Vec<Attribute>)],
+) -> TokenStream {
+    fn list(bebop: &Ident, attributes: &[Attribute]) -> TokenStream {
+        let attributes = attributes.iter().map(|Attribute { name, args }| {
+            let args = args.iter().map(|arg| match arg {
+                AttributeValue::Number(n) => quote!(#bebop::AttributeValue::Number(#n)),
+                AttributeValue::String(s) => quote!(#bebop::AttributeValue::String(#s)),
+                AttributeValue::Ident(i) => quote!(#bebop::AttributeValue::Ident(#i)),
+            });
+            quote!(#bebop::Attribute { name: #name, args: &[#(#args),*] })
+        });
+        quote!(&[#(#attributes),*])
+    }
+    let own = list(bebop, attributes);
+    let members = members
+        .iter()
+        .filter(|(_, attributes)| !attributes.is_empty())
+        .map(|(member, attributes)| {
+            let attributes = list(bebop, attributes);
+            quote!((#member, #attributes))
+        });
+    quote!(
+        #[allow(deprecated)]
+        impl #bebop::Attributes for #name {
+            const ATTRIBUTES: &'static [#bebop::Attribute] = #own;
+            const FIELD_ATTRIBUTES: &'static [(&'static str, &'static [#bebop::Attribute])] = &[#(#members),*];
+        }
+    )
+}
+
 impl<'a> Parser<'a> {
     pub fn skip_whitespace(&mut self) {
         loop {
             self.src = self.src.trim_start();
             if self.src.starts_with("//") {
                 let n = self.src.find(&['\r', '\n'][..]).unwrap_or(self.src.len());
+                if let Some(doc) = self.src[..n].strip_prefix("///") {
+                    if !doc.starts_with('/') {
+                        self.docs.push(doc.into());
+                    }
+                }
                 self.src = &self.src[n..];
             } else if self.src.starts_with("/*") {
                 let n = self.src.find("*/").map_or(self.src.len(), |n| n + 2);
+                let comment = &self.src[..n];
+                if let Some(doc) = comment
+                    .strip_prefix("/**")
+                    .and_then(|c| c.strip_suffix("*/"))
+                {
+                    if !doc.starts_with('*') && !doc.is_empty() {
+                        // Keep one leading space per line, like `///` comments.
+                        let lines: Vec<String> = doc
+                            .lines()
+                            .map(|line| {
+                                let line = line.trim();
+                                match line.strip_prefix('*') {
+                                    Some(rest) => rest.into(),
+                                    None if line.is_empty() => String::new(),
+                                    None => format!(" {}", line),
+                                }
+                            })
+                            .collect();
+                        let start = lines.iter().position(|l| !l.trim().is_empty());
+                        let end = lines.iter().rposition(|l| !l.trim().is_empty());
+                        if let (Some(start), Some(end)) = (start, end) {
+                            self.docs.extend_from_slice(&lines[start..=end]);
+                        }
+                    }
+                }
                 self.src = &self.src[n..];
             } else {
                 break;
@@ -40,6 +679,7 @@ impl<'a> Parser<'a> {
             None
         } else {
             self.skip_whitespace();
+            self.docs.clear();
             let n = self
                 .src
                 .find(|c: char| !c.is_alphanumeric() && c != '_')
@@ -67,7 +707,7 @@ impl<'a> Parser<'a> {
                         Err(_) => error!("invalid hexadecimal number"),
                     }
                 } else {
-                    match u32::from_str_radix(token, 10) {
+                    match token.parse() {
                         Ok(n) => Ok(n),
                         Err(_) => error!("invalid number"),
                     }
@@ -100,55 +740,120 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn parse_type(&mut self) -> Result<TokenStream, TokenStream> {
-        let mut t = match self.next_token() {
+    /// The traits of the definition that `name` refers to in this file.
+    ///
+    /// Like Rust, this looks in the module of this file before the modules it
+    /// imports. Definitions that come later have the traits found by the
+    /// previous pass, if any.
+    fn traits_of(&self, name: &Ident) -> Traits {
+        let paths = match &self.module {
+            Some(module) => std::iter::once(module)
+                .chain(&self.imports)
+                .map(|module| type_path(Some(module), name))
+                .collect(),
+            None => vec![name.to_string()],
+        };
+        let invocation = &self.invocation;
+        paths
+            .iter()
+            .find_map(|path| {
+                invocation
+                    .traits
+                    .get(path)
+                    .or_else(|| invocation.previous_traits.get(path))
+            })
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Parse a type, returning its Rust type and the traits it has.
+    pub fn parse_type(&mut self) -> Result<(TokenStream, Traits), TokenStream> {
+        self.parse_type_or_byte().map(|(t, traits, _)| (t, traits))
+    }
+
+    /// Like `parse_type`, but also returns whether the type is a single byte.
+    fn parse_type_or_byte(&mut self) -> Result<(TokenStream, Traits, bool), TokenStream> {
+        let mut byte = false;
+        let (mut t, mut traits) = match self.next_token() {
             Some("map") => {
                 self.expect("[")?;
-                let key = self.parse_type()?;
+                let (key, key_traits) = self.parse_type()?;
                 self.expect(",")?;
-                let value = self.parse_type()?;
+                let (value, value_traits) = self.parse_type()?;
                 self.expect("]")?;
-                quote!(std::collections::HashMap<#key, #value>)
+                let types = &self.invocation.types;
+                types.map(key, value, key_traits, value_traits)
             }
             Some("array") => {
                 self.expect("[")?;
-                let element = self.parse_type()?;
+                let (element, traits, byte) = self.parse_type_or_byte()?;
                 self.expect("]")?;
-                quote!(Vec<#element>)
-            }
-            Some("string") => quote!(String),
-            Some("bool") => quote!(bool),
-            Some("byte") => quote!(u8),
-            Some("uint8") => quote!(u8),
-            Some("int8") => quote!(i8),
-            Some("uint16") => quote!(u16),
-            Some("int16") => quote!(i16),
-            Some("uint32") => quote!(u32),
-            Some("int32") => quote!(i32),
-            Some("uint64") => quote!(u64),
-            Some("int64") => quote!(i64),
-            Some("float32") => quote!(f32),
-            Some("float64") => quote!(f64),
+                self.invocation.types.array(element, byte, traits)
+            }
+            Some("string") => self.invocation.types.string(),
+            Some("bool") => (quote!(bool), Traits::all(1)),
+            Some("byte") | Some("uint8") => {
+                byte = true;
+                (quote!(u8), Traits::all(1))
+            }
+            Some("int8") => (quote!(i8), Traits::all(1)),
+            Some("uint16") => (quote!(u16), Traits::all(2)),
+            Some("int16") => (quote!(i16), Traits::all(2)),
+            Some("uint32") => (quote!(u32), Traits::all(4)),
+            Some("int32") => (quote!(i32), Traits::all(4)),
+            Some("uint64") => (quote!(u64), Traits::all(8)),
+            Some("int64") => (quote!(i64), Traits::all(8)),
+            Some("float32") => (quote!(f32), Traits::float(4)),
+            Some("float64") => (quote!(f64), Traits::float(8)),
             Some("date") => {
-                let c = &self.crate_path;
-                quote!(#c::Date)
+                let c = &self.invocation.crate_path;
+                let traits = Traits {
+                    hash: false,
+                    ..Traits::all(8)
+                };
+                (quote!(#c::Date), traits)
             }
             Some("guid") => {
-                let c = &self.crate_path;
-                quote!(#c::Guid)
+                let c = &self.invocation.crate_path;
+                (quote!(#c::Guid), Traits::all(16))
             }
             Some(name) => {
                 let ident = Ident::new(name, Span::call_site());
-                quote!(#ident)
+                let traits = self.traits_of(&ident);
+                (quote!(#ident), traits)
             }
             None => return error!("missing type"),
         };
         while self.is_next("[") {
             self.expect("[")?;
-            self.expect("]")?;
-            t = quote!(Vec<#t>);
+            if self.is_next("]") {
+                self.expect("]")?;
+                (t, traits) = self.invocation.types.array(t, byte, traits);
+            } else {
+                let len = self.parse_number()? as usize;
+                self.expect("]")?;
+                t = quote!([#t; #len]);
+                traits.copy_size = traits.copy_size.map(|size| size * len);
+                // `Default` is only implemented for arrays of up to 32 elements.
+                traits.default &= len <= 32;
+            }
+            byte = false;
+        }
+        Ok((t, traits, byte))
+    }
+
+    /// Parse the type and name of a field of `definition`.
+    fn parse_field(
+        &mut self,
+        definition: &Ident,
+    ) -> Result<(Ident, TokenStream, Traits), TokenStream> {
+        let (t, traits) = self.parse_type()?;
+        let field = Ident::new(self.parse_identifier()?, Span::call_site());
+        match self.invocation.types.field(definition, &field) {
+            // Nothing is known about the traits of other types.
+            Some(t) => Ok((field, t.clone(), Traits::default())),
+            None => Ok((field, t, traits)),
         }
-        Ok(t)
     }
 
     pub fn is_next(&mut self, next: &str) -> bool {
@@ -164,211 +869,512 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn parse_opcode(&mut self) -> Result<Option<u32>, TokenStream> {
-        if !self.is_next("[") {
-            return Ok(None);
+    /// Take the doc comments before the next token, as `#[doc]` attributes.
+    pub fn parse_docs(&mut self) -> TokenStream {
+        self.skip_whitespace();
+        let docs = std::mem::take(&mut self.docs);
+        quote!(#(#[doc = #docs])*)
+    }
+
+    /// Parse any number of attributes, e.g. `[opcode(0x1)] [deprecated("reason")]`.
+    pub fn parse_attributes(&mut self) -> Result<Attributes, TokenStream> {
+        let mut attributes = Vec::new();
+        while self.is_next("[") {
+            self.expect("[")?;
+            let name = self.parse_identifier()?.to_string();
+            let mut args = Vec::new();
+            if self.is_next("(") {
+                self.expect("(")?;
+                while !self.is_next(")") {
+                    args.push(self.parse_attribute_value()?);
+                    if !self.is_next(")") {
+                        self.expect(",")?;
+                    }
+                }
+                self.expect(")")?;
+            }
+            self.expect("]")?;
+            attributes.push(Attribute { name, args });
         }
-        self.expect("[")?;
-        self.expect("opcode")?;
-        self.expect("(")?;
+        Ok(Attributes(attributes))
+    }
+
+    /// Parse an attribute argument: a number, a string literal or an identifier.
+    fn parse_attribute_value(&mut self) -> Result<AttributeValue, TokenStream> {
         self.skip_whitespace();
-        let opcode = if self.src.starts_with(|c: char| c.is_numeric()) {
-            self.parse_number()?
+        if self.src.starts_with(&['\'', '"'][..]) {
+            Ok(AttributeValue::String(self.parse_string_literal()?))
+        } else if self
+            .src
+            .starts_with(|c: char| c.is_alphabetic() || c == '_')
+        {
+            Ok(AttributeValue::Ident(self.parse_identifier()?.into()))
         } else {
-            let s = self.parse_string_literal()?;
-            match <[u8; 4]>::try_from(s.as_bytes()) {
-                Ok(bytes) => u32::from_le_bytes(bytes),
-                Err(_) => return error!("opcodes must be four bytes"),
+            let sign = if self.is_next("-") {
+                self.expect("-")?;
+                "-"
+            } else {
+                ""
+            };
+            let n = match self.next_token() {
+                Some(token) => match token.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(&format!("{}{}", sign, hex), 16),
+                    None => format!("{}{}", sign, token).parse(),
+                },
+                None => return error!("missing attribute argument"),
+            };
+            match n {
+                Ok(n) => Ok(AttributeValue::Number(n)),
+                Err(_) => error!("invalid attribute argument"),
             }
-        };
-        self.expect(")")?;
-        self.expect("]")?;
-        Ok(Some(opcode))
+        }
     }
 
-    pub fn parse_deprecated(&mut self) -> Result<Option<TokenStream>, TokenStream> {
-        if !self.is_next("[") {
-            return Ok(None);
-        }
-        self.expect("[")?;
-        self.expect("deprecated")?;
-        let attr = if self.is_next("]") {
-            quote!(#[deprecated])
-        } else {
-            self.expect("(")?;
-            self.skip_whitespace();
-            let message = self.parse_string_literal()?;
-            self.expect(")")?;
-            quote!(#[deprecated = #message])
-        };
-        self.expect("]")?;
-        Ok(Some(attr))
+    /// Record that type `name` is generated for `purpose`, failing if
+    /// another type with the same name is.
+    fn declare_type(&mut self, name: &Ident, purpose: String) -> Result<(), TokenStream> {
+        self.invocation
+            .declare_type(self.module.as_ref(), name, purpose)
+    }
+
+    fn parse_definition_name(&mut self) -> Result<Ident, TokenStream> {
+        let name = Ident::new(self.parse_identifier()?, Span::call_site());
+        self.declare_type(&name, format!("definition `{}`", name))?;
+        Ok(name)
     }
 
     pub fn parse_definition(&mut self) -> Result<(Ident, TokenStream), TokenStream> {
-        let opcode = self.parse_opcode()?;
-        let token = match self.next_token() {
-            Some("readonly") => self.next_token(),
-            t => t,
-        };
+        let docs = self.parse_docs();
+        let mut attributes = self.parse_attributes()?;
+        let opcode = attributes.opcode()?;
+        let deprecated = attributes.deprecated()?;
+        let docs = quote!(#docs #deprecated);
+        let attributes = attributes.custom()?;
+        let mut token = self.next_token();
+        let readonly = token == Some("readonly");
+        if readonly {
+            token = self.next_token();
+            if token != Some("struct") {
+                return error!("only structs can be readonly");
+            }
+        }
+        let (name, rust) = self.parse_definition_body(token, opcode, readonly, docs, attributes)?;
+        if let Some(opcode) = opcode {
+            let opcodes = &mut self.invocation.opcodes;
+            if let Some((_, other, _)) = opcodes.iter().find(|(o, _, _)| *o == opcode) {
+                return error!(
+                    "opcode {:#x} is used by both {} and {}",
+                    opcode, other, name
+                );
+            }
+            // Definitions in different modules can have the same name, so
+            // prefix the name of the dispatch enum variant with the module.
+            let (variant, path) = match &self.module {
+                Some(module) => {
+                    let prefix = module
+                        .to_string()
+                        .split('_')
+                        .map(|word| {
+                            let mut chars = word.chars();
+                            chars
+                                .next()
+                                .map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+                        })
+                        .collect::<String>();
+                    (format_ident!("{}{}", prefix, name), quote!(#module::#name))
+                }
+                None => (name.clone(), quote!(#name)),
+            };
+            opcodes.push((opcode, variant, path));
+        }
+        Ok((name, rust))
+    }
+
+    fn parse_definition_body(
+        &mut self,
+        token: Option<&'a str>,
+        opcode: Option<u32>,
+        readonly: bool,
+        docs: TokenStream,
+        attributes: Vec<Attribute>,
+    ) -> Result<(Ident, TokenStream), TokenStream> {
         match token {
             Some("enum") => {
                 if opcode.is_some() {
                     return error!("enums cannot have an opcode");
                 }
-                let name = Ident::new(self.parse_identifier()?, Span::call_site());
+                let name = self.parse_definition_name()?;
                 let mut names = Vec::new();
                 let mut values = Vec::new();
                 let mut attrs = Vec::new();
+                let mut default = None;
+                let mut member_attributes = Vec::new();
                 self.expect("{")?;
                 while !self.is_next("}") {
-                    attrs.push(self.parse_deprecated()?);
-                    names.push(Ident::new(self.parse_identifier()?, Span::call_site()));
+                    let docs = self.parse_docs();
+                    let mut attributes = self.parse_attributes()?;
+                    let is_default = attributes.flag("default")?;
+                    let deprecated = attributes.deprecated()?;
+                    attrs.push(quote!(#docs #deprecated));
+                    let member = self.parse_identifier()?;
+                    member_attributes.push((member.to_string(), attributes.custom()?));
+                    names.push(Ident::new(member, Span::call_site()));
                     self.expect("=")?;
                     values.push(self.parse_number()?);
                     self.expect(";")?;
+                    if is_default {
+                        if default.is_some() {
+                            return error!("{} has more than one default member", name);
+                        }
+                        default = Some(names.len() - 1);
+                    }
                 }
                 self.expect("}")?;
-                let bebop = &self.crate_path;
+                // Without a `[default]` member, the member with value 0 is the default.
+                if let Some(i) = default.or_else(|| values.iter().position(|&v| v == 0)) {
+                    attrs[i].extend(quote!(#[default]));
+                }
+                let traits = Traits {
+                    default: default.is_some() || values.contains(&0),
+                    ..Traits::all(4)
+                };
+                self.invocation
+                    .add_traits(self.module.as_ref(), &name, traits);
+                let type_attrs = self
+                    .invocation
+                    .type_attrs(&name, &traits.derives(&["Clone", "Debug", "PartialEq"]));
+                let bebop = &self.invocation.crate_path;
+                let vis = &self.invocation.vis;
+                let name_str = name.to_string();
+                let attributes = attributes_impl(bebop, &name, &attributes, &member_attributes);
                 Ok((
                     name.clone(),
                     quote!(
                         #[repr(u32)]
-                        #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-                        pub enum #name {
+                        #docs
+                        #type_attrs
+                        #vis enum #name {
                             #(#attrs #names = #values,)*
                         }
+                        #attributes
+                        #[allow(deprecated)]
                         impl #bebop::Bebop for #name {
-                            fn read_from(reader: &mut #bebop::Reader) -> Result<Self, #bebop::Error> {
-                                let value: u32 = reader.read()?;
-                                match value {
-                                    #(#values => Ok(Self::#names),)*
-                                    _ => Err(#bebop::Error::UnknownEnumValue),
-                                }
+                            fn read_from<R: std::io::Read>(reader: &mut #bebop::Reader<R>) -> Result<Self, #bebop::Error> {
+                                reader.read_definition(#name_str, |reader| {
+                                    let value: u32 = reader.read()?;
+                                    match value {
+                                        #(#values => Ok(Self::#names),)*
+                                        _ => Err(reader.error(#bebop::ErrorKind::UnknownEnumValue)),
+                                    }
+                                })
                             }
-                            fn write_into(&self, writer: &mut #bebop::Writer) {
+                            fn write_into<W: std::io::Write>(&self, writer: &mut #bebop::Writer<W>) -> Result<(), #bebop::Error> {
                                 let value: u32 = match self {
                                     #(Self::#names => #values,)*
                                 };
-                                writer.write(&value);
+                                writer.write(&value)
+                            }
+                            const FIXED_SIZE: Option<usize> = Some(4);
+                            fn encoded_size(&self) -> usize {
+                                4
                             }
                         }
                     ),
                 ))
             }
             Some("struct") => {
-                let name = Ident::new(self.parse_identifier()?, Span::call_site());
+                let name = self.parse_definition_name()?;
                 self.expect("{")?;
+                let mut attrs = Vec::new();
                 let mut types = Vec::new();
                 let mut names = Vec::new();
+                let mut traits = Traits::all(0);
+                let mut copies = Vec::new();
+                let mut field_attributes = Vec::new();
                 while !self.is_next("}") {
-                    types.push(self.parse_type()?);
-                    names.push(Ident::new(self.parse_identifier()?, Span::call_site()));
+                    let docs = self.parse_docs();
+                    let mut attributes = self.parse_attributes()?;
+                    let deprecated = attributes.deprecated()?;
+                    attrs.push(quote!(#docs #deprecated));
+                    let attributes = attributes.custom()?;
+                    let (field, t, field_traits) = self.parse_field(&name)?;
+                    field_attributes.push((field.to_string(), attributes));
+                    copies.push(field_traits.copy_size.is_some());
+                    types.push(t);
+                    names.push(field);
+                    traits = traits.and(field_traits);
                     self.expect(";")?;
                 }
                 self.expect("}")?;
-                let bebop = &self.crate_path;
+                let traits = traits.of_struct();
+                let view_name = format_ident!("{}View", name);
+                self.declare_type(&view_name, format!("the view of `{}`", name))?;
+                self.invocation
+                    .add_traits(self.module.as_ref(), &name, traits);
+                let type_attrs = self
+                    .invocation
+                    .type_attrs(&name, &traits.derives(&["Clone", "Debug", "PartialEq"]));
+                let bebop = &self.invocation.crate_path;
+                let vis = &self.invocation.vis;
+                let name_str = name.to_string();
+                let attributes = attributes_impl(bebop, &name, &attributes, &field_attributes);
+                let name_strs = names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>();
                 let opcode = opcode.into_iter();
+                let view = struct_view(bebop, vis, &name, &attrs, &names, &types);
+                // Fields of readonly structs are private, and can only be
+                // set by the constructor. Field `x` is read with `get_x()`, so
+                // fields cannot clash with other methods. `Copy` fields are
+                // returned by value.
+                let (field_vis, getters) = if readonly {
+                    let refs = copies
+                        .iter()
+                        .map(|&copy| if copy { quote!() } else { quote!(&) })
+                        .collect::<Vec<_>>();
+                    let getter_names = names.iter().map(|name| format_ident!("get_{}", name));
+                    let getters = quote!(
+                        #(
+                            #attrs
+                            #vis const fn #getter_names(&self) -> #refs #types {
+                                #refs self.#names
+                            }
+                        )*
+                    );
+                    (quote!(), getters)
+                } else {
+                    (vis.clone(), quote!())
+                };
                 Ok((
                     name.clone(),
                     quote!(
-                        #[derive(Clone, Debug, PartialEq)]
-                        pub struct #name {
-                            #(pub #names: #types,)*
+                        #docs
+                        #type_attrs
+                        #vis struct #name {
+                            #(#attrs #field_vis #names: #types,)*
+                        }
+                        #[allow(deprecated)]
+                        impl #name {
+                            #[allow(clippy::too_many_arguments)]
+                            #vis const fn new(#(#names: #types),*) -> Self {
+                                Self { #(#names),* }
+                            }
+                            #getters
                         }
+                        #attributes
+                        #view
                         #(
+                            #[allow(deprecated)]
                             impl #bebop::Opcode for #name {
                                 const OPCODE: u32 = #opcode;
                             }
                         )*
+                        #[allow(deprecated)]
                         impl #bebop::Bebop for #name {
-                            fn read_from(reader: &mut #bebop::Reader) -> Result<Self, #bebop::Error> {
-                                Ok(Self {
-                                    #(#names: reader.read()?,)*
+                            fn read_from<R: std::io::Read>(reader: &mut #bebop::Reader<R>) -> Result<Self, #bebop::Error> {
+                                reader.read_definition(#name_str, |reader| {
+                                    Ok(Self {
+                                        #(#names: reader.read_field(#name_strs)?,)*
+                                    })
                                 })
                             }
-                            fn write_into(&self, writer: &mut #bebop::Writer) {
-                                #(writer.write(&self.#names);)*
+                            fn write_into<W: std::io::Write>(&self, writer: &mut #bebop::Writer<W>) -> Result<(), #bebop::Error> {
+                                #(writer.write(&self.#names)?;)*
+                                Ok(())
+                            }
+                            const FIXED_SIZE: Option<usize> = {
+                                let size = Some(0);
+                                #(let size = #bebop::__export::add_fixed_size(size, <#types as #bebop::Bebop>::FIXED_SIZE);)*
+                                size
+                            };
+                            fn encoded_size(&self) -> usize {
+                                match Self::FIXED_SIZE {
+                                    Some(size) => size,
+                                    None => 0 #(+ #bebop::Bebop::encoded_size(&self.#names))*,
+                                }
+                            }
+                            fn encoded_sizes(&self, sizes: &mut #bebop::Sizes) -> usize {
+                                match Self::FIXED_SIZE {
+                                    Some(size) => size,
+                                    None => 0 #(+ #bebop::Bebop::encoded_sizes(&self.#names, sizes))*,
+                                }
+                            }
+                            fn skip<R: std::io::Read>(reader: &mut #bebop::Reader<R>) -> Result<(), #bebop::Error> {
+                                match Self::FIXED_SIZE {
+                                    Some(size) => reader.skip_raw(size),
+                                    None => {
+                                        #(reader.skip::<#types>()?;)*
+                                        Ok(())
+                                    }
+                                }
+                            }
+                            fn check(reader: &mut #bebop::Reader<&[u8]>) -> Result<(), #bebop::Error> {
+                                reader.read_definition(#name_str, |reader| {
+                                    #(reader.check_field::<#types>(#name_strs)?;)*
+                                    Ok(())
+                                })
                             }
                         }
                     ),
                 ))
             }
             Some("message") => {
-                let name = Ident::new(self.parse_identifier()?, Span::call_site());
+                let name = self.parse_definition_name()?;
                 self.expect("{")?;
                 let mut attrs = Vec::new();
                 let mut indices = Vec::new();
                 let mut types = Vec::new();
                 let mut names = Vec::new();
+                let mut traits = Traits::all(0).without_copy();
+                let mut field_attributes = Vec::new();
+                let mut deprecated_fields = Vec::new();
                 while !self.is_next("}") {
-                    attrs.push(self.parse_deprecated()?);
+                    let docs = self.parse_docs();
+                    let mut attributes = self.parse_attributes()?;
+                    let deprecated = attributes.deprecated()?;
+                    let attributes = attributes.custom()?;
+                    deprecated_fields.push(deprecated.is_some());
+                    attrs.push(quote!(#docs #deprecated));
                     let index = self.parse_number()?;
-                    if index < 1 || index > 255 {
+                    if !(1..=255).contains(&index) {
                         return error!("message field index must be in the range 1..=255, but got {}", index);
                     }
                     indices.push(index as u8);
                     self.expect("-")?;
                     self.expect(">")?;
-                    types.push(self.parse_type()?);
-                    names.push(Ident::new(self.parse_identifier()?, Span::call_site()));
+                    let (field, t, field_traits) = self.parse_field(&name)?;
+                    if field == "build" {
+                        return error!("message `{}` cannot have a field named `build`", name);
+                    }
+                    field_attributes.push((field.to_string(), attributes));
+                    types.push(t);
+                    names.push(field);
+                    traits = traits.and(field_traits);
                     self.expect(";")?;
                 }
                 self.expect("}")?;
-                let bebop = &self.crate_path;
+                // All fields are optional, so messages are always `Default`.
+                let traits = Traits {
+                    default: true,
+                    ..traits
+                };
+                let view_name = format_ident!("{}View", name);
+                self.declare_type(&view_name, format!("the view of `{}`", name))?;
+                let builder_name = format_ident!("{}Builder", name);
+                self.declare_type(&builder_name, format!("the builder of `{}`", name))?;
+                self.invocation
+                    .add_traits(self.module.as_ref(), &name, traits);
+                let type_attrs = self
+                    .invocation
+                    .type_attrs(&name, &traits.derives(&["Clone", "Debug", "PartialEq"]));
+                let bebop = &self.invocation.crate_path;
+                let vis = &self.invocation.vis;
+                let name_str = name.to_string();
+                let attributes = attributes_impl(bebop, &name, &attributes, &field_attributes);
+                let name_strs = names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>();
                 let opcode = opcode.into_iter();
+                let view = message_view(bebop, vis, &name, &attrs, &indices, &names, &types);
+                let builder = message_builder(vis, &name, &attrs, &names, &types);
+                // Deprecated fields are skipped when decoding, and never encoded.
+                let (live, dead): (Vec<_>, Vec<_>) =
+                    (0..names.len()).partition(|&i| !deprecated_fields[i]);
+                let live_indices = live.iter().map(|&i| indices[i]).collect::<Vec<_>>();
+                let live_names = live.iter().map(|&i| &names[i]).collect::<Vec<_>>();
+                let live_name_strs = live.iter().map(|&i| &name_strs[i]);
+                let dead_indices = dead.iter().map(|&i| indices[i]);
+                let dead_types = dead.iter().map(|&i| &types[i]);
+                let dead_name_strs = dead.iter().map(|&i| &name_strs[i]);
                 Ok((
                     name.clone(),
                     quote!(
-                        #[derive(Clone, Debug, Default, PartialEq)]
-                        pub struct #name {
-                            #(#attrs pub #names: Option<#types>,)*
+                        #docs
+                        #type_attrs
+                        #vis struct #name {
+                            #(#attrs #vis #names: Option<#types>,)*
                         }
+                        #builder
+                        #attributes
+                        #view
                         #(
+                            #[allow(deprecated)]
                             impl #bebop::Opcode for #name {
                                 const OPCODE: u32 = #opcode;
                             }
                         )*
+                        #[allow(deprecated)]
                         impl #bebop::Bebop for #name {
-                            fn read_from(reader: &mut #bebop::Reader) -> Result<Self, #bebop::Error> {
-                                let len: u32 = reader.read()?;
-                                let bytes = reader.read_raw(len as usize)?;
-                                let mut reader = #bebop::Reader::new(bytes);
-                                let mut value = Self::default();
-                                loop {
-                                    match reader.read::<u8>()? {
-                                        0 => break,
-                                        #(#indices => value.#names = Some(reader.read()?),)*
-                                        _ => break, // unknown field. skip to end of message
-                                    }
-                                }
-                                Ok(value)
+                            fn read_from<R: std::io::Read>(reader: &mut #bebop::Reader<R>) -> Result<Self, #bebop::Error> {
+                                reader.read_definition(#name_str, |reader| {
+                                    let mut value = Self::default();
+                                    reader.read_message(|reader, index| {
+                                        match index {
+                                            #(#live_indices => value.#live_names = Some(reader.read_field(#live_name_strs)?),)*
+                                            #(#dead_indices => reader.skip_field::<#dead_types>(#dead_name_strs)?,)*
+                                            _ => return Ok(false),
+                                        }
+                                        Ok(true)
+                                    })?;
+                                    Ok(value)
+                                })
                             }
-                            fn write_into(&self, writer: &mut #bebop::Writer) {
-                                let offset = writer.bytes().len();
-                                writer.write(&0u32); // placeholder for the size
+                            fn write_into<W: std::io::Write>(&self, writer: &mut #bebop::Writer<W>) -> Result<(), #bebop::Error> {
+                                let size = writer.nested_size(self);
+                                writer.write_len(size - 4)?;
                                 #(
-                                    if let Some(field) = &self.#names {
-                                        writer.write::<u8>(&#indices);
-                                        writer.write(field);
+                                    if let Some(field) = &self.#live_names {
+                                        writer.write::<u8>(&#live_indices)?;
+                                        writer.write(field)?;
                                     }
                                 )*
-                                writer.write(&0u8);
-                                // fill in the size in the placeholder we wrote before
-                                let size = (writer.bytes().len() - 4 - offset) as u32;
-                                writer.bytes_mut()[offset..][..4].copy_from_slice(&size.to_le_bytes());
+                                writer.write(&0u8)
+                            }
+                            fn encoded_size(&self) -> usize {
+                                // size, fields with their index, and the terminating zero
+                                4 #(+ self.#live_names.as_ref().map_or(0, |field| 1 + #bebop::Bebop::encoded_size(field)))* + 1
+                            }
+                            fn encoded_sizes(&self, sizes: &mut #bebop::Sizes) -> usize {
+                                let i = sizes.start(self);
+                                let size = 4 #(+ self.#live_names.as_ref().map_or(0, |field| 1 + #bebop::Bebop::encoded_sizes(field, sizes)))* + 1;
+                                sizes.finish(i, size)
+                            }
+                            fn skip<R: std::io::Read>(reader: &mut #bebop::Reader<R>) -> Result<(), #bebop::Error> {
+                                let len: u32 = reader.read()?;
+                                reader.skip_raw(len as usize)
+                            }
+                            fn check(reader: &mut #bebop::Reader<&[u8]>) -> Result<(), #bebop::Error> {
+                                reader.read_definition(#name_str, |reader| {
+                                    reader.read_message(|reader, index| {
+                                        match index {
+                                            #(#indices => reader.check_field::<#types>(#name_strs)?,)*
+                                            _ => return Ok(false),
+                                        }
+                                        Ok(true)
+                                    })
+                                })
                             }
                         }
                     ),
                 ))
             }
             Some("union") => {
-                let name = Ident::new(self.parse_identifier()?, Span::call_site());
+                let name = self.parse_definition_name()?;
                 self.expect("{")?;
                 let mut defs = TokenStream::new();
                 let mut indices = Vec::new();
                 let mut names = Vec::new();
+                let mut attrs = Vec::new();
+                let mut traits = Traits::all(0).without_copy();
+                let mut branch_attributes = Vec::new();
                 while !self.is_next("}") {
+                    let docs = self.parse_docs();
+                    let mut attributes = self.parse_attributes()?;
+                    let deprecated = attributes.deprecated()?;
+                    attrs.push(quote!(#docs #deprecated));
+                    let attributes = attributes.custom()?;
                     let index = self.parse_number()?;
                     if index > 255 {
                         return error!("union index must be <= 255, but got {}", index);
@@ -377,50 +1383,105 @@ impl<'a> Parser<'a> {
                     self.expect("-")?;
                     self.expect(">")?;
                     let (field_name, field_def) = self.parse_definition()?;
+                    branch_attributes.push((field_name.to_string(), attributes));
+                    let path = type_path(self.module.as_ref(), &field_name);
+                    let branch_traits = self.invocation.traits[&path];
+                    traits = traits.and(branch_traits.without_copy());
                     names.push(field_name);
                     defs.extend(field_def);
                 }
                 self.expect("}")?;
-                let bebop = &self.crate_path;
+                let traits = Traits {
+                    default: false,
+                    ..traits
+                };
+                self.invocation
+                    .add_traits(self.module.as_ref(), &name, traits);
+                let type_attrs = self
+                    .invocation
+                    .type_attrs(&name, &traits.derives(&["Clone", "Debug", "PartialEq"]));
+                let bebop = &self.invocation.crate_path;
+                let vis = &self.invocation.vis;
+                let name_str = name.to_string();
+                let attributes = attributes_impl(bebop, &name, &attributes, &branch_attributes);
+                let name_strs = names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>();
                 let opcode = opcode.into_iter();
                 Ok((
                     name.clone(),
                     quote!(
                         #defs
-                        #[derive(Clone, Debug, PartialEq)]
-                        pub enum #name {
-                            #(#names(#names),)*
+                        #docs
+                        #type_attrs
+                        #vis enum #name {
+                            #(#attrs #names(#names),)*
                         }
+                        #attributes
                         #(
+                            #[allow(deprecated)]
                             impl #bebop::Opcode for #name {
                                 const OPCODE: u32 = #opcode;
                             }
                         )*
+                        #[allow(deprecated)]
                         impl #bebop::Bebop for #name {
-                            fn read_from(reader: &mut #bebop::Reader) -> Result<Self, #bebop::Error> {
-                                let len: u32 = reader.read()?;
-                                let tag: u8 = reader.read()?;
-                                let bytes = reader.read_raw(len as usize)?;
-                                let mut reader = #bebop::Reader::new(bytes);
-                                match tag {
-                                    #(#indices => Ok(Self::#names(reader.read()?)),)*
-                                    _ => Err(#bebop::Error::UnknownUnionTag),
-                                }
+                            fn read_from<R: std::io::Read>(reader: &mut #bebop::Reader<R>) -> Result<Self, #bebop::Error> {
+                                reader.read_definition(#name_str, |reader| {
+                                    let len: u32 = reader.read()?;
+                                    let tag: u8 = reader.read()?;
+                                    reader.read_nested(len as usize, |reader| {
+                                        let value = match tag {
+                                            #(#indices => Self::#names(reader.read_field(#name_strs)?),)*
+                                            _ => return Err(reader.error(#bebop::ErrorKind::UnknownUnionTag)),
+                                        };
+                                        reader.check_end(#bebop::ErrorKind::UnionLengthMismatch)?;
+                                        Ok(value)
+                                    })
+                                })
                             }
-                            fn write_into(&self, writer: &mut #bebop::Writer) {
-                                let offset = writer.bytes().len();
-                                writer.write(&0u32); // placeholder for the size
+                            fn write_into<W: std::io::Write>(&self, writer: &mut #bebop::Writer<W>) -> Result<(), #bebop::Error> {
+                                let size = writer.nested_size(self);
+                                writer.write_len(size - 5)?;
                                 match self {
                                     #(
                                         Self::#names(v) => {
-                                            writer.write::<u8>(&#indices);
-                                            writer.write(v);
+                                            writer.write::<u8>(&#indices)?;
+                                            writer.write(v)
                                         }
                                     )*
                                 }
-                                // fill in the size in the placeholder we wrote before
-                                let size = (writer.bytes().len() - 5 - offset) as u32;
-                                writer.bytes_mut()[offset..][..4].copy_from_slice(&size.to_le_bytes());
+                            }
+                            fn encoded_size(&self) -> usize {
+                                // size, tag, and the value itself
+                                4 + 1 + match self {
+                                    #(Self::#names(v) => #bebop::Bebop::encoded_size(v),)*
+                                }
+                            }
+                            fn encoded_sizes(&self, sizes: &mut #bebop::Sizes) -> usize {
+                                let i = sizes.start(self);
+                                let size = 4 + 1 + match self {
+                                    #(Self::#names(v) => #bebop::Bebop::encoded_sizes(v, sizes),)*
+                                };
+                                sizes.finish(i, size)
+                            }
+                            fn skip<R: std::io::Read>(reader: &mut #bebop::Reader<R>) -> Result<(), #bebop::Error> {
+                                let len: u32 = reader.read()?;
+                                reader.skip_raw(1 + len as usize)
+                            }
+                            fn check(reader: &mut #bebop::Reader<&[u8]>) -> Result<(), #bebop::Error> {
+                                reader.read_definition(#name_str, |reader| {
+                                    let len: u32 = reader.read()?;
+                                    let tag: u8 = reader.read()?;
+                                    reader.read_nested(len as usize, |reader| {
+                                        match tag {
+                                            #(#indices => reader.check_field::<#names>(#name_strs)?,)*
+                                            _ => return Err(reader.error(#bebop::ErrorKind::UnknownUnionTag)),
+                                        }
+                                        reader.check_end(#bebop::ErrorKind::UnionLengthMismatch)
+                                    })
+                                })
                             }
                         }
                     ),
@@ -455,16 +1516,434 @@ impl<'a> Parser<'a> {
                     Ok(src) => src,
                     Err(e) => return error!("unable to open {:?}: {}", file, e),
                 };
-                let mut parser = Parser {
-                    file: &file,
-                    crate_path: self.crate_path.clone(),
-                    src: &src,
-                };
-                rust.extend(parser.parse_file()?);
+                rust.extend(self.invocation.parse_file(&file, &src)?);
+                if self.invocation.modules {
+                    let module = module_name(&file)?;
+                    // Types of indirect imports can be used as well.
+                    let indirect = self.invocation.module_imports[&module.to_string()].clone();
+                    for module in std::iter::once(module).chain(indirect) {
+                        if !self.imports.contains(&module) {
+                            self.imports.push(module);
+                        }
+                    }
+                }
             } else {
                 rust.extend(self.parse_definition()?.1);
             }
         }
+        for module in &self.imports {
+            rust.extend(quote!(
+                #[allow(unused_imports)]
+                use super::#module::*;
+            ));
+        }
         Ok(rust)
     }
 }
+
+/// A builder for a message, and helpers to set and clear its fields.
+///
+/// Field `x` is set with `x()` on the builder, and with `with_x()` on the
+/// message.
+fn message_builder(
+    vis: &TokenStream,
+    name: &Ident,
+    attrs: &[TokenStream],
+    names: &[Ident],
+    types: &[TokenStream],
+) -> TokenStream {
+    let builder = format_ident!("{}Builder", name);
+    let doc = format!("A builder for [`{}`].", name);
+    let with = names.iter().map(|name| format_ident!("with_{}", name));
+    let set = names.iter().map(|name| format_ident!("set_{}", name));
+    let clear = names.iter().map(|name| format_ident!("clear_{}", name));
+    let take = names.iter().map(|name| format_ident!("take_{}", name));
+    quote!(
+        #[allow(deprecated)]
+        impl #name {
+            #vis fn builder() -> #builder {
+                <#builder as Default>::default()
+            }
+            #(
+                #attrs
+                #vis fn #with(mut self, #names: #types) -> Self {
+                    self.#names = Some(#names);
+                    self
+                }
+                #attrs
+                #vis fn #set(&mut self, #names: #types) {
+                    self.#names = Some(#names);
+                }
+                #attrs
+                #vis fn #clear(&mut self) {
+                    self.#names = None;
+                }
+                #attrs
+                #vis fn #take(&mut self) -> Option<#types> {
+                    self.#names.take()
+                }
+            )*
+        }
+        #[doc = #doc]
+        #[derive(Clone, Debug, Default)]
+        #[allow(deprecated)]
+        #vis struct #builder {
+            message: #name,
+        }
+        #[allow(deprecated)]
+        impl #builder {
+            #(
+                #attrs
+                #vis fn #names(mut self, #names: #types) -> Self {
+                    self.message.#names = Some(#names);
+                    self
+                }
+            )*
+            #vis fn build(self) -> #name {
+                self.message
+            }
+        }
+    )
+}
+
+/// A view of an encoded `name`, with `methods` to decode its fields.
+fn view_type(bebop: &Ident, vis: &TokenStream, name: &Ident, methods: TokenStream) -> TokenStream {
+    let view = format_ident!("{}View", name);
+    let doc = format!(
+        "A view of an encoded [`{}`], decoding fields on access.",
+        name
+    );
+    let new_doc = format!("Fails if `bytes` does not start with a whole [`{}`].", name);
+    let name_str = name.to_string();
+    quote!(
+        #[doc = #doc]
+        #[derive(Clone, Copy, Debug)]
+        #[allow(deprecated)]
+        #vis struct #view<'a> {
+            bytes: &'a [u8],
+        }
+        #[allow(deprecated)]
+        impl<'a> #view<'a> {
+            #[doc = #new_doc]
+            pub fn new(bytes: &'a [u8]) -> Result<Self, #bebop::Error> {
+                let mut reader = #bebop::Reader::new(bytes);
+                reader.skip::<#name>().map_err(|e| e.in_definition(#name_str))?;
+                Ok(Self { bytes: &bytes[..reader.offset()] })
+            }
+            pub const fn bytes(&self) -> &'a [u8] {
+                self.bytes
+            }
+            pub fn decode(&self) -> Result<#name, #bebop::Error> {
+                #bebop::Bebop::decode(self.bytes)
+            }
+            #methods
+        }
+    )
+}
+
+/// A type that gives access to the fields of an encoded struct,
+/// decoding only the requested field.
+///
+/// Field `x` is read with `get_x()`, so fields cannot clash with the other
+/// methods.
+fn struct_view(
+    bebop: &Ident,
+    vis: &TokenStream,
+    name: &Ident,
+    attrs: &[TokenStream],
+    names: &[Ident],
+    types: &[TokenStream],
+) -> TokenStream {
+    let name_str = name.to_string();
+    let name_strs = names.iter().map(|name| name.to_string());
+    let getters = names.iter().map(|name| format_ident!("get_{}", name));
+    let preceding = (0..types.len()).map(|i| &types[..i]);
+    let methods = quote!(
+        #(
+            #attrs
+            pub fn #getters(&self) -> Result<#types, #bebop::Error> {
+                #bebop::Reader::new(self.bytes).read_definition(#name_str, |reader| {
+                    #(reader.skip::<#preceding>()?;)*
+                    reader.read_field(#name_strs)
+                })
+            }
+        )*
+    );
+    view_type(bebop, vis, name, methods)
+}
+
+/// A type that gives access to the fields of an encoded message,
+/// decoding only the requested field.
+///
+/// Errors have the same offsets as when decoding the whole message.
+fn message_view(
+    bebop: &Ident,
+    vis: &TokenStream,
+    name: &Ident,
+    attrs: &[TokenStream],
+    indices: &[u8],
+    names: &[Ident],
+    types: &[TokenStream],
+) -> TokenStream {
+    let name_str = name.to_string();
+    let name_strs = names
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let getters = names.iter().map(|name| format_ident!("get_{}", name));
+    let methods = quote!(
+        /// Find the offset of the value of the field with the given index.
+        #[allow(dead_code)]
+        fn find(&self, wanted: u8) -> Result<Option<usize>, #bebop::Error> {
+            let mut reader = #bebop::Reader::new(self.bytes);
+            // The length, which `new` already checked.
+            reader.read::<u32>()?;
+            loop {
+                let index = reader.read::<u8>()?;
+                if index == wanted {
+                    return Ok(Some(reader.offset()));
+                }
+                match index {
+                    #(#indices => reader.skip_field::<#types>(#name_strs)?,)*
+                    _ => return Ok(None), // end of message, or unknown field
+                }
+            }
+        }
+        #(
+            #attrs
+            pub fn #getters(&self) -> Result<Option<#types>, #bebop::Error> {
+                match self.find(#indices).map_err(|e| e.in_definition(#name_str))? {
+                    Some(offset) => {
+                        let mut reader = #bebop::Reader::new(self.bytes);
+                        reader.skip_raw(offset)?;
+                        reader
+                            .read_definition(#name_str, |reader| reader.read_field(#name_strs))
+                            .map(Some)
+                    }
+                    None => Ok(None),
+                }
+            }
+        )*
+    );
+    view_type(bebop, vis, name, methods)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Invocation;
+    use proc_macro2::{Ident, Span, TokenStream};
+    use quote::quote;
+    use std::path::Path;
+
+    /// Parse `src` as a file on its own, returning any error message.
+    fn parse(src: &str) -> Result<TokenStream, String> {
+        let mut invocation = Invocation::new(Ident::new("bebop", Span::call_site()));
+        invocation
+            .parse_file(Path::new("test.bop"), src)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn type_overrides() {
+        let mut invocation = Invocation::new(Ident::new("bebop", Span::call_site()));
+        invocation.types.bytes = Some(quote!(Bytes));
+        let fields = [("A.b", quote!(Box<[u8]>)), ("A.x", quote!(u8))];
+        for (key, t) in fields {
+            invocation.types.fields.insert(key.into(), t);
+        }
+        let src = "struct A { byte[] a; uint8[] b; int8[] c; byte[][] d; byte[4] e; }";
+        let rust = invocation
+            .parse_file(Path::new("test.bop"), src)
+            .unwrap()
+            .to_string();
+        assert!(rust.contains("a : Bytes ,"), "{}", rust);
+        assert!(rust.contains("b : Box < [u8] > ,"), "{}", rust);
+        assert!(rust.contains("c : Vec < i8 > ,"), "{}", rust);
+        assert!(rust.contains("d : Vec < Bytes > ,"), "{}", rust);
+        assert!(rust.contains("e : [u8 ; 4usize] ,"), "{}", rust);
+        let error = invocation.types.check_fields().unwrap_err().to_string();
+        assert!(error.contains("`A.x`, which is not a field"), "{}", error);
+    }
+
+    #[test]
+    fn override_traits() {
+        let mut invocation = Invocation::new(Ident::new("bebop", Span::call_site()));
+        invocation.types.string = Some(quote!(Name));
+        invocation.types.map = Some(quote!(Map<_, _>));
+        let src = "struct A { string a; } struct B { map[byte, byte] b; } struct C { int32 c; }";
+        let rust = invocation
+            .parse_file(Path::new("test.bop"), src)
+            .unwrap()
+            .to_string();
+        // Only `C` gets more than the traits derived for every type.
+        let base = "# [derive (Clone , Debug , PartialEq ,)] # [allow (deprecated)] pub struct";
+        assert!(rust.contains(&format!("{} A", base)), "{}", rust);
+        assert!(rust.contains(&format!("{} B", base)), "{}", rust);
+        assert!(!rust.contains(&format!("{} C", base)), "{}", rust);
+    }
+
+    #[test]
+    fn unused_attrs() {
+        let mut invocation = Invocation::new(Ident::new("bebop", Span::call_site()));
+        for name in ["A", "Record", "Typo"] {
+            invocation.attrs.insert(name.into(), (Vec::new(), quote!()));
+        }
+        invocation
+            .parse_file(Path::new("test.bop"), "struct A { int32 a; }")
+            .unwrap();
+        invocation.dispatch_enum(&Ident::new("Record", Span::call_site()));
+        let error = invocation.check_attrs().unwrap_err().to_string();
+        assert!(
+            error.contains("`Typo`, which is not a generated type"),
+            "{}",
+            error
+        );
+        invocation.attrs.remove("Typo");
+        assert!(invocation.check_attrs().is_ok());
+    }
+
+    #[test]
+    fn readonly_getters() {
+        // Getters cannot clash with the constructor or the `Bebop` methods.
+        let rust = parse("readonly struct A { uint32 new; uint32 encode; }")
+            .unwrap()
+            .to_string();
+        assert!(rust.contains("fn get_new (& self)"), "{}", rust);
+        assert!(rust.contains("fn get_encode (& self)"), "{}", rust);
+    }
+
+    #[test]
+    fn message_build_field() {
+        let error = parse("message A { 1 -> uint32 build; }").unwrap_err();
+        assert!(
+            error.contains("message `A` cannot have a field named `build`"),
+            "{}",
+            error
+        );
+        assert!(parse("struct A { uint32 build; }").is_ok());
+    }
+
+    #[test]
+    fn builder_name_clash() {
+        let error =
+            parse("message Job { 1 -> uint32 id; } struct JobBuilder { int32 x; }").unwrap_err();
+        assert!(
+            error.contains("`JobBuilder` is used for both the builder of `Job` and definition"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn attribute_errors() {
+        let errors = [
+            (
+                "[opcode(1)] [opcode(2)] struct A {}",
+                "duplicate `opcode` attribute",
+            ),
+            ("[opcode] struct A {}", "`opcode` needs a value"),
+            (
+                "[opcode(1, 2)] struct A {}",
+                "`opcode` takes a single number or string",
+            ),
+            (
+                "[opcode(-1)] struct A {}",
+                "opcodes must fit in a `uint32`, but got -1",
+            ),
+            (
+                "[deprecated(1)] struct A {}",
+                "`deprecated` takes at most one string",
+            ),
+            ("[default] struct A {}", "`default` cannot be used here"),
+            (
+                "enum E { [default(1)] A = 0; }",
+                "`default` does not take arguments",
+            ),
+            ("[x(1 2)] struct A {}", r#"expected \",\", but got \"2\""#),
+        ];
+        for (src, expected) in errors {
+            let error = parse(src).unwrap_err();
+            assert!(error.contains(expected), "{}: {}", src, error);
+        }
+    }
+
+    #[test]
+    fn doc_comments() {
+        let src = "
+            /// A point.
+            ///
+            ///  In 2D.
+            struct Point {
+                /** The x
+                 * coordinate. */
+                int32 x;
+                /**/
+                //// Not a doc comment.
+                /*** Nor this. */
+                int32 y;
+            }
+        ";
+        let rust = parse(src).unwrap().to_string();
+        let docs = r#"# [doc = " A point."] # [doc = ""] # [doc = "  In 2D."]"#;
+        assert!(rust.contains(docs), "{}", rust);
+        let docs = r#"# [doc = " The x"] # [doc = " coordinate."] pub x"#;
+        assert!(rust.contains(docs), "{}", rust);
+        assert!(rust.contains(", pub y"), "{}", rust);
+    }
+
+    #[test]
+    fn view_name_clash() {
+        let error = parse("struct Point { int32 x; } struct PointView { int32 y; }").unwrap_err();
+        assert!(
+            error.contains(
+                "`PointView` is used for both the view of `Point` and definition `PointView`"
+            ),
+            "{}",
+            error
+        );
+        let error = parse("enum PointView { A = 0; } message Point { 1 -> int32 x; }").unwrap_err();
+        assert!(
+            error.contains(
+                "`PointView` is used for both definition `PointView` and the view of `Point`"
+            ),
+            "{}",
+            error
+        );
+        let error = parse("struct Point { int32 x; } struct Point { int32 y; }").unwrap_err();
+        assert!(
+            error.contains("`Point` is defined more than once"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn module_name_clash() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/imports/module_clash.bop");
+        let src = std::fs::read_to_string(&file).unwrap();
+        let mut invocation = Invocation::new(Ident::new("bebop", Span::call_site()));
+        invocation.modules = true;
+        let error = invocation.parse_file(&file, &src).unwrap_err().to_string();
+        assert!(
+            error.contains("`common` is used for both the module of "),
+            "{}",
+            error
+        );
+        assert!(error.contains("nested/common.bop"), "{}", error);
+    }
+
+    #[test]
+    fn circular_import() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/imports/cycle_a.bop");
+        let src = std::fs::read_to_string(&file).unwrap();
+        let mut invocation = Invocation::new(Ident::new("bebop", Span::call_site()));
+        let error = invocation.parse_file(&file, &src).unwrap_err().to_string();
+        assert!(error.contains("circular import: "), "{}", error);
+        assert!(
+            error.contains("cycle_a.bop -> ") && error.contains("cycle_b.bop -> "),
+            "{}",
+            error
+        );
+        assert!(invocation.import_stack.is_empty());
+    }
+}
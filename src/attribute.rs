@@ -0,0 +1,31 @@
+/// A user-defined attribute from a schema, e.g. `[table("users")]` or
+/// `[range(-1, 10)]`.
+///
+/// The built-in `opcode`, `deprecated` and `default` attributes are turned
+/// into code and are not listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Attribute {
+    pub name: &'static str,
+    /// The arguments between the parentheses, if any.
+    pub args: &'static [AttributeValue],
+}
+
+/// An argument of an [`Attribute`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttributeValue {
+    Number(i64),
+    String(&'static str),
+    /// A bare identifier, e.g. `users` in `[table(users)]`.
+    Ident(&'static str),
+}
+
+/// The user-defined attributes of a definition and its members.
+///
+/// Implemented by every type generated by `bebop!`.
+pub trait Attributes {
+    /// The attributes of the definition itself.
+    const ATTRIBUTES: &'static [Attribute];
+    /// The attributes of the fields, enum members or union branches that
+    /// have any, by name.
+    const FIELD_ATTRIBUTES: &'static [(&'static str, &'static [Attribute])];
+}
@@ -0,0 +1,124 @@
+use super::frame::FrameHeader;
+use super::Bebop;
+use super::Dispatch;
+use super::Error;
+use super::FrameDecoder;
+use super::Reader;
+use super::Writer;
+use bytes::{BufMut, BytesMut};
+use std::marker::PhantomData;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A [`tokio_util::codec`] codec for [`Frame`](crate::Frame)s with only a
+/// length prefix, each containing a `T`.
+#[derive(Debug)]
+pub struct FrameCodec<T> {
+    max_frame_len: usize,
+    phantom: PhantomData<fn(T) -> T>,
+}
+
+/// A [`tokio_util::codec`] codec for [`Frame`](crate::Frame)s with a length
+/// prefix and an opcode, each containing one of the definitions of `D`.
+#[derive(Debug)]
+pub struct DispatchCodec<D> {
+    max_frame_len: usize,
+    phantom: PhantomData<fn(D) -> D>,
+}
+
+macro_rules! impl_codec {
+    ($codec:ident, $opcode:literal) => {
+        impl<T> $codec<T> {
+            pub const fn new() -> Self {
+                Self {
+                    max_frame_len: FrameDecoder::DEFAULT_MAX_FRAME_LEN,
+                    phantom: PhantomData,
+                }
+            }
+
+            /// Like [`FrameDecoder::max_frame_len`].
+            pub const fn max_frame_len(mut self, max: usize) -> Self {
+                self.max_frame_len = max;
+                self
+            }
+
+            /// Split the next complete frame off of `src`, returning its
+            /// opcode and payload.
+            fn next_frame(
+                &self,
+                src: &mut BytesMut,
+            ) -> Result<Option<(Option<u32>, BytesMut)>, Error> {
+                let header = match FrameHeader::parse(&src[..], $opcode, self.max_frame_len)? {
+                    Some(header) => header,
+                    None => return Ok(None),
+                };
+                if src.len() < header.frame_len() {
+                    src.reserve(header.frame_len() - src.len());
+                    return Ok(None);
+                }
+                let mut payload = src.split_to(header.frame_len());
+                let _ = payload.split_to(header.len);
+                Ok(Some((header.opcode, payload)))
+            }
+        }
+
+        impl<T> Default for $codec<T> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl<T> Clone for $codec<T> {
+            fn clone(&self) -> Self {
+                Self {
+                    max_frame_len: self.max_frame_len,
+                    phantom: PhantomData,
+                }
+            }
+        }
+    };
+}
+
+impl_codec!(FrameCodec, false);
+impl_codec!(DispatchCodec, true);
+
+impl<T: Bebop> Decoder for FrameCodec<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, Error> {
+        match self.next_frame(src)? {
+            Some((_, payload)) => Ok(Some(Reader::new(&payload).read()?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T: Bebop> Encoder<T> for FrameCodec<T> {
+    type Error = Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Error> {
+        dst.reserve(4 + item.encoded_size());
+        Writer::from_writer(dst.writer()).write_frame(&item)
+    }
+}
+
+impl<D: Dispatch> Decoder for DispatchCodec<D> {
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        match self.next_frame(src)? {
+            Some((opcode, payload)) => Ok(Some(D::decode(opcode.unwrap(), &payload)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<D: Dispatch> Encoder<D> for DispatchCodec<D> {
+    type Error = Error;
+
+    fn encode(&mut self, item: D, dst: &mut BytesMut) -> Result<(), Error> {
+        dst.reserve(8 + item.encoded_size());
+        Writer::from_writer(dst.writer()).write_dispatch_frame(&item)
+    }
+}
@@ -0,0 +1,50 @@
+use super::Bebop;
+use super::Error;
+use super::ErrorKind;
+use super::Opcode;
+use super::Reader;
+use std::collections::HashMap;
+
+type Handler<'a> = Box<dyn FnMut(&mut Reader<&[u8]>) -> Result<(), Error> + 'a>;
+
+/// Calls a handler for a value based on its opcode.
+///
+/// Use this rather than a generated [`Dispatch`](crate::Dispatch) enum when
+/// the set of handled definitions is only known at runtime.
+#[derive(Default)]
+pub struct Dispatcher<'a> {
+    handlers: HashMap<u32, Handler<'a>>,
+}
+
+impl<'a> Dispatcher<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `handler` to be called with values of type `T`.
+    ///
+    /// Panics if a handler for the same opcode was already registered.
+    pub fn on<T: Bebop + Opcode>(&mut self, mut handler: impl FnMut(T) + 'a) -> &mut Self {
+        let previous = self.handlers.insert(
+            T::OPCODE,
+            Box::new(move |reader| {
+                handler(reader.read()?);
+                Ok(())
+            }),
+        );
+        assert!(
+            previous.is_none(),
+            "duplicate handler for opcode {:#x}",
+            T::OPCODE
+        );
+        self
+    }
+
+    /// Decode `bytes` as the type registered for `opcode` and call its handler.
+    pub fn dispatch(&mut self, opcode: u32, bytes: &[u8]) -> Result<(), Error> {
+        match self.handlers.get_mut(&opcode) {
+            Some(handler) => handler(&mut Reader::new(bytes)),
+            None => Err(ErrorKind::UnknownOpcode.into()),
+        }
+    }
+}
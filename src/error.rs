@@ -1,27 +1,182 @@
 use std::fmt;
+use std::io;
 use std::str::Utf8Error;
+use std::string::FromUtf8Error;
+
+/// An error, with information about where it happened, if available.
+///
+/// Use [`kind`](Error::kind) to find out what kind of error it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    context: Option<Box<Context>>,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Error {
+pub enum ErrorKind {
     UnexpectedEnd,
     InvalidUtf8,
     UnknownEnumValue,
     UnknownUnionTag,
+    UnknownOpcode,
+    FrameTooLarge,
+    BufferTooSmall,
+    LengthOverflow,
+    TrailingBytes,
+    InvalidBool,
+    UnsortedFields,
+    UnsortedElements,
+    UnionLengthMismatch,
+    InvalidPadding,
+    Io(io::ErrorKind),
 }
 
-impl fmt::Display for Error {
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Context {
+    offset: Option<usize>,
+    definition: Option<&'static str>,
+    /// Innermost first.
+    path: Vec<PathSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PathSegment {
+    Field(&'static str),
+    Index(usize),
+}
+
+impl Error {
+    pub const fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The position in the input at which decoding failed, if known.
+    pub fn offset(&self) -> Option<usize> {
+        self.context.as_ref()?.offset
+    }
+
+    /// The path to the value that could not be decoded,
+    /// e.g. `MediaMessage.data.fragment[3]`, if known.
+    pub fn path(&self) -> Option<String> {
+        let context = self.context.as_ref()?;
+        let mut path = String::from(context.definition?);
+        for segment in context.path.iter().rev() {
+            match segment {
+                PathSegment::Field(name) => path += &format!(".{}", name),
+                PathSegment::Index(i) => path += &format!("[{}]", i),
+            }
+        }
+        Some(path)
+    }
+
+    fn context(&mut self) -> &mut Context {
+        self.context.get_or_insert_with(Default::default)
+    }
+
+    /// Record the position in the input at which the error happened.
+    pub fn at_offset(mut self, offset: usize) -> Self {
+        self.context().offset = Some(offset);
+        self
+    }
+
+    /// Record that the error happened while reading `definition`.
+    ///
+    /// Only the outermost definition is kept, as the start of the path.
+    pub fn in_definition(mut self, definition: &'static str) -> Self {
+        self.context().definition = Some(definition);
+        self
+    }
+
+    /// Record that the error happened in field `field`.
+    pub fn in_field(mut self, field: &'static str) -> Self {
+        self.context().path.push(PathSegment::Field(field));
+        self
+    }
+
+    /// Record that the error happened in element `index` of an array or map.
+    pub fn at_index(mut self, index: usize) -> Self {
+        self.context().path.push(PathSegment::Index(index));
+        self
+    }
+}
+
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::UnexpectedEnd => f.write_str("unexpected end of message"),
-            Error::InvalidUtf8 => f.write_str("invalid UTF-8 in string"),
-            Error::UnknownEnumValue => f.write_str("unknown enum value"),
-            Error::UnknownUnionTag => f.write_str("unknown union tag"),
+            ErrorKind::UnexpectedEnd => f.write_str("unexpected end of message"),
+            ErrorKind::InvalidUtf8 => f.write_str("invalid UTF-8 in string"),
+            ErrorKind::UnknownEnumValue => f.write_str("unknown enum value"),
+            ErrorKind::UnknownUnionTag => f.write_str("unknown union tag"),
+            ErrorKind::UnknownOpcode => f.write_str("unknown opcode"),
+            ErrorKind::FrameTooLarge => f.write_str("frame exceeds the maximum length"),
+            ErrorKind::BufferTooSmall => f.write_str("buffer too small"),
+            ErrorKind::LengthOverflow => f.write_str("length does not fit in 32 bits"),
+            ErrorKind::TrailingBytes => {
+                f.write_str("unexpected bytes after the end of the message")
+            }
+            ErrorKind::InvalidBool => f.write_str("boolean is neither 0 nor 1"),
+            ErrorKind::UnsortedFields => {
+                f.write_str("message fields are duplicated or out of order")
+            }
+            ErrorKind::UnsortedElements => {
+                f.write_str("set elements or map keys are duplicated or out of order")
+            }
+            ErrorKind::UnionLengthMismatch => {
+                f.write_str("union length does not match its contents")
+            }
+            ErrorKind::InvalidPadding => {
+                f.write_str("unexpected bytes after the end of message fields")
+            }
+            ErrorKind::Io(kind) => write!(f, "I/O error: {}", kind),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(path) = self.path() {
+            write!(f, " in {}", path)?;
+        }
+        if let Some(offset) = self.offset() {
+            write!(f, " at byte {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error {
+            kind,
+            context: None,
         }
     }
 }
 
 impl From<Utf8Error> for Error {
     fn from(_: Utf8Error) -> Error {
-        Error::InvalidUtf8
+        ErrorKind::InvalidUtf8.into()
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(_: FromUtf8Error) -> Error {
+        ErrorKind::InvalidUtf8.into()
+    }
+}
+
+impl From<io::Error> for ErrorKind {
+    fn from(e: io::Error) -> ErrorKind {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => ErrorKind::UnexpectedEnd,
+            kind => ErrorKind::Io(kind),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        ErrorKind::from(e).into()
     }
 }
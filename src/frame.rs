@@ -0,0 +1,176 @@
+use super::Bebop;
+use super::Dispatch;
+use super::Error;
+use super::ErrorKind;
+use super::Reader;
+use std::io::{self, Read};
+
+/// A single frame taken from a stream of length-delimited records.
+///
+/// On the wire, a frame is its payload length as a `u32`, followed by the
+/// opcode as a `u32` if the stream uses opcodes, followed by the payload.
+/// Frames are written with [`Writer::write_frame`](crate::Writer::write_frame)
+/// or [`Writer::write_opcode_frame`](crate::Writer::write_opcode_frame).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub opcode: Option<u32>,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn decode<T: Bebop>(&self) -> Result<T, Error> {
+        Reader::new(&self.payload).read()
+    }
+
+    /// Decode the payload as whichever definition the opcode selects.
+    ///
+    /// A frame without an opcode results in [`ErrorKind::UnknownOpcode`].
+    pub fn dispatch<D: Dispatch>(&self) -> Result<D, Error> {
+        match self.opcode {
+            Some(opcode) => D::decode(opcode, &self.payload),
+            None => Err(ErrorKind::UnknownOpcode.into()),
+        }
+    }
+}
+
+/// Splits a stream of bytes into [`Frame`]s.
+///
+/// Bytes can be pushed in pieces of any size, and complete frames are taken
+/// out once all their bytes have arrived.
+#[derive(Debug, Clone)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    /// Where the bytes of the next frame start in `buffer`. The bytes
+    /// before it belong to frames that were already taken.
+    start: usize,
+    opcode: bool,
+    max_frame_len: usize,
+}
+
+impl FrameDecoder {
+    pub const DEFAULT_MAX_FRAME_LEN: usize = 16 << 20;
+
+    /// A decoder for frames with only a length prefix.
+    pub const fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            start: 0,
+            opcode: false,
+            max_frame_len: Self::DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+
+    /// A decoder for frames with a length prefix followed by an opcode.
+    pub const fn with_opcode() -> Self {
+        Self {
+            buffer: Vec::new(),
+            start: 0,
+            opcode: true,
+            max_frame_len: Self::DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+
+    /// Reject frames with a payload larger than `max` bytes,
+    /// rather than buffering them.
+    pub const fn max_frame_len(mut self, max: usize) -> Self {
+        self.max_frame_len = max;
+        self
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        // Only move the remaining bytes to the front once they are at most
+        // half the buffer, so that taking frames does not move them again
+        // for every frame.
+        if self.start > 0 && self.start >= self.buffer.len() - self.start {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Bytes that have been pushed but are not yet part of a returned frame.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer[self.start..]
+    }
+
+    /// Take the next frame, or `None` if it has not fully arrived yet.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let buffered = self.buffered();
+        let header = match FrameHeader::parse(buffered, self.opcode, self.max_frame_len)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if buffered.len() < header.frame_len() {
+            return Ok(None);
+        }
+        let payload = buffered[header.len..header.frame_len()].to_vec();
+        self.start += header.frame_len();
+        if self.start == self.buffer.len() {
+            self.buffer.clear();
+            self.start = 0;
+        }
+        Ok(Some(Frame {
+            opcode: header.opcode,
+            payload,
+        }))
+    }
+
+    /// Read from `r` until the next frame is complete.
+    ///
+    /// Returns `None` if `r` ends cleanly between two frames.
+    pub fn read_frame<R: Read>(&mut self, mut r: R) -> Result<Option<Frame>, Error> {
+        let mut buffer = [0; 8192];
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                return Ok(Some(frame));
+            }
+            match r.read(&mut buffer) {
+                Ok(0) if self.buffered().is_empty() => return Ok(None),
+                Ok(0) => return Err(ErrorKind::UnexpectedEnd.into()),
+                Ok(n) => self.push(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) struct FrameHeader {
+    pub opcode: Option<u32>,
+    /// The length of the header itself.
+    pub len: usize,
+    pub payload_len: usize,
+}
+
+impl FrameHeader {
+    /// Parse the header at the start of `bytes`, or `None` if it is incomplete.
+    pub fn parse(bytes: &[u8], opcode: bool, max_frame_len: usize) -> Result<Option<Self>, Error> {
+        let len = if opcode { 8 } else { 4 };
+        if bytes.len() < len {
+            return Ok(None);
+        }
+        let mut reader = Reader::new(bytes);
+        let payload_len: u32 = reader.read()?;
+        let opcode = if opcode { Some(reader.read()?) } else { None };
+        let payload_len = payload_len as usize;
+        if payload_len > max_frame_len {
+            return Err(ErrorKind::FrameTooLarge.into());
+        }
+        Ok(Some(Self {
+            opcode,
+            len,
+            payload_len,
+        }))
+    }
+
+    /// The length of the header and the payload together.
+    pub fn frame_len(&self) -> usize {
+        self.len + self.payload_len
+    }
+}
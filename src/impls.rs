@@ -0,0 +1,152 @@
+//! `Bebop` impls for types that can be used instead of the default ones
+//! with the `types` and `fields` options of [`bebop!`](crate::bebop).
+
+use super::Bebop;
+use super::Error;
+use super::Reader;
+use super::Writer;
+use std::io::{Read, Write};
+use std::rc::Rc;
+use std::sync::Arc;
+
+macro_rules! impl_str {
+    ($($t:ty)*) => ($(
+        impl Bebop for $t {
+            fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+                String::read_from(reader).map(<$t>::from)
+            }
+            fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+                writer.write_len(self.len())?;
+                writer.write_raw(self.as_bytes())
+            }
+            fn encoded_size(&self) -> usize {
+                4 + self.len()
+            }
+            fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+                String::skip(reader)
+            }
+            fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+                String::check(reader)
+            }
+        }
+    )*);
+}
+
+impl_str!(Box<str> Rc<str> Arc<str>);
+
+#[cfg(feature = "bytes")]
+impl Bebop for bytes::Bytes {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        let len: u32 = reader.read()?;
+        reader.read_raw_vec(len as usize).map(bytes::Bytes::from)
+    }
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_len(self.len())?;
+        writer.write_raw(self)
+    }
+    fn encoded_size(&self) -> usize {
+        4 + self.len()
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        let len: u32 = reader.read()?;
+        reader.skip_raw(len as usize)
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        Self::skip(reader)
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A: smallvec::Array> Bebop for smallvec::SmallVec<A>
+where
+    A::Item: Bebop,
+{
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        let len: u32 = reader.read()?;
+        let mut vec = Self::new();
+        for i in 0..len as usize {
+            vec.push(reader.read().map_err(|e: Error| e.at_index(i))?);
+        }
+        Ok(vec)
+    }
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_len(self.len())?;
+        for item in self {
+            writer.write(item)?;
+        }
+        Ok(())
+    }
+    fn encoded_size(&self) -> usize {
+        match A::Item::FIXED_SIZE {
+            Some(size) => 4 + size * self.len(),
+            None => 4 + self.iter().map(Bebop::encoded_size).sum::<usize>(),
+        }
+    }
+    fn encoded_sizes(&self, sizes: &mut super::Sizes) -> usize {
+        match A::Item::FIXED_SIZE {
+            Some(size) => 4 + size * self.len(),
+            None => {
+                4 + self
+                    .iter()
+                    .map(|item| item.encoded_sizes(sizes))
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        Vec::<A::Item>::skip(reader)
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        Vec::<A::Item>::check(reader)
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<K: Bebop + std::hash::Hash + Eq, V: Bebop> Bebop for indexmap::IndexMap<K, V> {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        let len: u32 = reader.read()?;
+        let mut map = Self::with_capacity(reader.capacity_hint(len));
+        for i in 0..len as usize {
+            let key = reader.read().map_err(|e: Error| e.at_index(i))?;
+            let value = reader.read().map_err(|e: Error| e.at_index(i))?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_len(self.len())?;
+        for (key, value) in self {
+            writer.write(key)?;
+            writer.write(value)?;
+        }
+        Ok(())
+    }
+    fn encoded_size(&self) -> usize {
+        match (K::FIXED_SIZE, V::FIXED_SIZE) {
+            (Some(k), Some(v)) => 4 + (k + v) * self.len(),
+            _ => {
+                4 + self
+                    .iter()
+                    .map(|(key, value)| key.encoded_size() + value.encoded_size())
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn encoded_sizes(&self, sizes: &mut super::Sizes) -> usize {
+        match (K::FIXED_SIZE, V::FIXED_SIZE) {
+            (Some(k), Some(v)) => 4 + (k + v) * self.len(),
+            _ => {
+                4 + self
+                    .iter()
+                    .map(|(key, value)| key.encoded_sizes(sizes) + value.encoded_sizes(sizes))
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        std::collections::HashMap::<K, V>::skip(reader)
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        std::collections::HashMap::<K, V>::check(reader)
+    }
+}
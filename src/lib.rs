@@ -1,43 +1,192 @@
+mod attribute;
+#[cfg(feature = "tokio")]
+mod codec;
 mod date;
+mod dispatch;
 mod error;
+mod frame;
 mod guid;
+mod impls;
 mod reader;
 mod writer;
 
+pub use attribute::{Attribute, AttributeValue, Attributes};
+#[cfg(feature = "tokio")]
+pub use codec::{DispatchCodec, FrameCodec};
 pub use date::Date;
-pub use error::Error;
+pub use dispatch::Dispatcher;
+pub use error::{Error, ErrorKind};
+pub use frame::{Frame, FrameDecoder};
 pub use guid::Guid;
 pub use reader::Reader;
-pub use writer::Writer;
+pub use writer::{Sizes, Writer};
 
-use std::collections::HashMap;
-use std::convert::TryFrom;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::hash::Hash;
+use std::io::{Read, Write};
 
 #[doc(hidden)]
 pub mod __export {
     pub use bebop_macro::read_bebop;
+
+    /// Used to calculate `FIXED_SIZE` of structs.
+    pub const fn add_fixed_size(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            _ => None,
+        }
+    }
 }
 
+/// Generate Rust types for the definitions in one or more `.bop` files.
+///
+/// Options:
+///
+/// - `dispatch = Name`: generate a [`Dispatch`] enum of all definitions with an opcode.
+///   With `modules`, the variants are prefixed with the module name, e.g. `CommonHeader`.
+/// - `types(string = T, bytes = T, array = T, map = T)`: use other Rust types
+///   than `String`, `Vec<u8>`, `Vec` and `HashMap`. `_` in `array` and `map`
+///   stands for the element, key and value types, e.g. `map = indexmap::IndexMap<_, _>`.
+///   `HashMap`s are encoded in an arbitrary order, so use
+///   `map = std::collections::BTreeMap<_, _>` or `deterministic` when the same
+///   value must always have the same encoding.
+/// - `deterministic`: use `BTreeMap` rather than `HashMap` for maps, unless
+///   `types(map = T)` is given.
+/// - `fields(Definition.field = T)`: use another Rust type for a single field.
+///   Definitions containing a type from `types` or `fields` only derive
+///   `Clone`, `Debug` and `PartialEq`; use `derive` or `attrs` for the rest.
+/// - `modules`: put the definitions of each file in a module named after the
+///   file, e.g. `common` for `common.bop`, so that files can define the same
+///   names. Definitions from imported files are brought in with `use`.
+///   Two files with the same name in different directories are an error.
+/// - `module = name`: put all generated code in a module called `name`.
+/// - `vis = pub(crate)`: the visibility of generated types and fields, `pub` by default.
+/// - `derive(Eq, Hash)`: derive more traits for all generated types.
+/// - `attrs(Name = #[attr])`: add attributes to generated type `Name`.
+///   Traits derived here are not derived automatically as well.
 #[macro_export]
 macro_rules! bebop {
-    ($($path:literal),* $(,)?) => (
-        $($crate::__export::read_bebop!($crate $path);)*
+    ($($input:tt)*) => (
+        $crate::__export::read_bebop!($crate $($input)*);
     );
 }
 
 pub trait Bebop: Sized {
-    fn read_from(reader: &mut Reader) -> Result<Self, Error>;
-    fn write_into(&self, writer: &mut Writer);
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error>;
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error>;
+
+    /// The encoded size of every value of this type, if they all have the same size.
+    ///
+    /// This lets the size of e.g. a `Vec<u32>` be calculated without
+    /// looking at every element.
+    const FIXED_SIZE: Option<usize> = None;
+
+    /// Whether any `FIXED_SIZE` bytes are a valid value, so that checking a
+    /// value only needs to skip it.
+    const ALWAYS_VALID: bool = false;
+
+    /// The exact number of bytes `write_into` will write.
+    ///
+    /// This is what lets messages and unions write their length prefix up
+    /// front, rather than patching it in afterwards.
+    fn encoded_size(&self) -> usize;
+
+    /// Like [`encoded_size`](Bebop::encoded_size), but also record the size
+    /// of every message and union in `self` in `sizes`, in the order they
+    /// are written.
+    ///
+    /// This lets [`Writer`] size nested messages and unions in one pass.
+    /// Types that contain other values must forward to them.
+    fn encoded_sizes(&self, sizes: &mut Sizes) -> usize {
+        let _ = sizes;
+        self.encoded_size()
+    }
+
+    /// Skip over a value without decoding it.
+    ///
+    /// Messages and unions are skipped in one go using their length prefix,
+    /// without looking at their contents.
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        match Self::FIXED_SIZE {
+            Some(size) => reader.skip_raw(size),
+            None => Self::read_from(reader).map(drop),
+        }
+    }
+
+    /// Check that a value is well-formed, without decoding it.
+    ///
+    /// This rejects everything that `read_from` rejects, but does not
+    /// allocate.
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        Self::read_from(reader).map(drop)
+    }
 
     fn decode(bytes: &[u8]) -> Result<Self, Error> {
         Reader::new(bytes).read()
     }
 
+    /// Check that `bytes` starts with a well-formed value, without decoding it.
+    fn validate(bytes: &[u8]) -> Result<(), Error> {
+        Reader::new(bytes).check::<Self>()
+    }
+
+    /// Decode only the canonical encoding of a value, with nothing after it.
+    ///
+    /// See [`Reader::strict`] for what is rejected in addition to what
+    /// [`decode`](Bebop::decode) rejects.
+    fn decode_strict(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes).strict();
+        let value = reader.read()?;
+        if !reader.bytes_left().is_empty() {
+            return Err(reader.error(ErrorKind::TrailingBytes));
+        }
+        Ok(value)
+    }
+
+    /// Decode directly from `r`, reading only as much as is needed.
+    fn decode_from<R: Read>(r: R) -> Result<Self, Error> {
+        Reader::from_reader(r).read()
+    }
+
+    /// Panics if a string or collection is too long for its length to fit
+    /// in a `u32`. Use [`try_encode`](Bebop::try_encode) to handle that instead.
     fn encode(&self) -> Vec<u8> {
-        let mut writer = Writer::new();
-        writer.write(self);
-        writer.finish()
+        self.try_encode()
+            .unwrap_or_else(|e| panic!("unable to encode: {}", e))
+    }
+
+    fn try_encode(&self) -> Result<Vec<u8>, Error> {
+        let mut writer = Writer::with_capacity(self.encoded_size());
+        writer.write(self)?;
+        Ok(writer.finish())
+    }
+
+    /// Append the encoding to `buffer`, reusing its allocation if it is big enough.
+    ///
+    /// Panics under the same conditions as [`encode`](Bebop::encode).
+    fn encode_into(&self, buffer: &mut Vec<u8>) {
+        buffer.reserve(self.encoded_size());
+        Writer::from_writer(buffer)
+            .write(self)
+            .unwrap_or_else(|e| panic!("unable to encode: {}", e))
+    }
+
+    /// Encode into the start of `buffer`, returning the number of bytes written.
+    ///
+    /// Nothing is written if `buffer` is too small, in which case this
+    /// returns [`ErrorKind::BufferTooSmall`].
+    fn encode_to_slice(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let size = self.encoded_size();
+        if buffer.len() < size {
+            return Err(ErrorKind::BufferTooSmall.into());
+        }
+        Writer::from_writer(buffer).write(self)?;
+        Ok(size)
+    }
+
+    /// Encode directly into `w`, without first collecting the whole encoding in memory.
+    fn encode_to<W: Write>(&self, w: W) -> Result<(), Error> {
+        Writer::from_writer(w).write(self)
     }
 }
 
@@ -45,24 +194,66 @@ pub trait Opcode {
     const OPCODE: u32;
 }
 
+/// One of a set of definitions with an [`Opcode`], selected by that opcode.
+///
+/// `bebop!("file.bop", dispatch = Name)` generates an enum called `Name`
+/// that implements this trait, with a variant for every definition with an
+/// opcode in the given files and their imports.
+pub trait Dispatch: Sized {
+    fn read_from<R: Read>(opcode: u32, reader: &mut Reader<R>) -> Result<Self, Error>;
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error>;
+    fn encoded_size(&self) -> usize;
+    fn opcode(&self) -> u32;
+
+    fn decode(opcode: u32, bytes: &[u8]) -> Result<Self, Error> {
+        Self::read_from(opcode, &mut Reader::new(bytes))
+    }
+
+    /// Panics under the same conditions as [`Bebop::encode`].
+    fn encode(&self) -> Vec<u8> {
+        self.try_encode()
+            .unwrap_or_else(|e| panic!("unable to encode: {}", e))
+    }
+
+    fn try_encode(&self) -> Result<Vec<u8>, Error> {
+        let mut writer = Writer::with_capacity(self.encoded_size());
+        self.write_into(&mut writer)?;
+        Ok(writer.finish())
+    }
+}
+
 impl Bebop for bool {
-    fn read_from(reader: &mut Reader) -> Result<Self, Error> {
-        Ok(reader.read_raw(1)?[0] != 0)
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        match reader.read_raw_array::<1>()?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ if reader.is_strict() => Err(reader.error(ErrorKind::InvalidBool)),
+            _ => Ok(true),
+        }
     }
-    fn write_into(&self, writer: &mut Writer) {
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
         writer.write_raw(&[*self as u8])
     }
+    const FIXED_SIZE: Option<usize> = Some(1);
+    fn encoded_size(&self) -> usize {
+        1
+    }
 }
 
 macro_rules! impl_int {
     ($($t:ty)*) => ($(
         impl Bebop for $t {
-            fn read_from(reader: &mut Reader) -> Result<Self, Error> {
-                Ok(<$t>::from_le_bytes(*reader.read_raw_array()?))
+            fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+                Ok(<$t>::from_le_bytes(reader.read_raw_array()?))
             }
-            fn write_into(&self, writer: &mut Writer) {
+            fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
                 writer.write_raw(&self.to_le_bytes())
             }
+            const FIXED_SIZE: Option<usize> = Some(std::mem::size_of::<$t>());
+            const ALWAYS_VALID: bool = true;
+            fn encoded_size(&self) -> usize {
+                std::mem::size_of::<$t>()
+            }
         }
     )*);
 }
@@ -70,73 +261,374 @@ macro_rules! impl_int {
 impl_int!(u8 i8 u16 i16 u32 i32 u64 i64 usize isize f32 f64);
 
 impl Bebop for String {
-    fn read_from(reader: &mut Reader) -> Result<Self, Error> {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        let offset = reader.offset();
         let len: u32 = reader.read()?;
-        let bytes = reader.read_raw(len as usize)?;
-        Ok(String::from(std::str::from_utf8(bytes)?))
+        let bytes = reader.read_raw_vec(len as usize)?;
+        String::from_utf8(bytes).map_err(|_| Error::from(ErrorKind::InvalidUtf8).at_offset(offset))
+    }
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_len(self.len())?;
+        writer.write_raw(self.as_bytes())
     }
-    fn write_into(&self, writer: &mut Writer) {
-        writer.write(&u32::try_from(self.len()).unwrap());
-        writer.write_raw(self.as_bytes());
+    fn encoded_size(&self) -> usize {
+        4 + self.len()
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        let len: u32 = reader.read()?;
+        reader.skip_raw(len as usize)
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        let offset = reader.offset();
+        let len: u32 = reader.read()?;
+        let bytes = reader.read_raw(len as usize)?;
+        match std::str::from_utf8(bytes) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::from(ErrorKind::InvalidUtf8).at_offset(offset)),
+        }
     }
 }
 
 impl Bebop for Guid {
-    fn read_from(reader: &mut Reader) -> Result<Self, Error> {
-        Ok(Guid::from(*reader.read_raw_array()?))
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        Ok(Guid::from(reader.read_raw_array()?))
     }
-    fn write_into(&self, writer: &mut Writer) {
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
         writer.write_raw(&<[u8; 16]>::from(*self))
     }
+    const FIXED_SIZE: Option<usize> = Some(16);
+    const ALWAYS_VALID: bool = true;
+    fn encoded_size(&self) -> usize {
+        16
+    }
 }
 
 impl Bebop for Date {
-    fn read_from(reader: &mut Reader) -> Result<Self, Error> {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
         Ok(Date(reader.read()?))
     }
-    fn write_into(&self, writer: &mut Writer) {
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
         writer.write(&self.0)
     }
+    const FIXED_SIZE: Option<usize> = Some(8);
+    const ALWAYS_VALID: bool = true;
+    fn encoded_size(&self) -> usize {
+        8
+    }
 }
 
 impl<T: Bebop> Bebop for Vec<T> {
-    fn read_from(reader: &mut Reader) -> Result<Self, Error> {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
         let len: u32 = reader.read()?;
-        let mut vec = Vec::with_capacity(len as usize);
-        for _ in 0..len {
-            vec.push(reader.read()?);
+        let mut vec = Vec::with_capacity(reader.capacity_hint(len));
+        for i in 0..len as usize {
+            vec.push(reader.read().map_err(|e: Error| e.at_index(i))?);
         }
         Ok(vec)
     }
-    fn write_into(&self, writer: &mut Writer) {
-        writer.write(&u32::try_from(self.len()).unwrap());
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_len(self.len())?;
         for item in self {
-            writer.write(item);
+            writer.write(item)?;
+        }
+        Ok(())
+    }
+    fn encoded_size(&self) -> usize {
+        match T::FIXED_SIZE {
+            Some(size) => 4 + size * self.len(),
+            None => 4 + self.iter().map(Bebop::encoded_size).sum::<usize>(),
+        }
+    }
+    fn encoded_sizes(&self, sizes: &mut Sizes) -> usize {
+        match T::FIXED_SIZE {
+            Some(size) => 4 + size * self.len(),
+            None => {
+                4 + self
+                    .iter()
+                    .map(|item| item.encoded_sizes(sizes))
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        let len: u32 = reader.read()?;
+        match T::FIXED_SIZE {
+            Some(size) => match size.checked_mul(len as usize) {
+                Some(n) => reader.skip_raw(n),
+                None => Err(reader.error(ErrorKind::UnexpectedEnd)),
+            },
+            None => {
+                for i in 0..len as usize {
+                    reader.skip::<T>().map_err(|e| e.at_index(i))?;
+                }
+                Ok(())
+            }
+        }
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        let len: u32 = reader.read()?;
+        match T::FIXED_SIZE {
+            // e.g. `byte[]`, which can be skipped in one go.
+            Some(size) if T::ALWAYS_VALID => match size.checked_mul(len as usize) {
+                Some(n) => reader.read_raw(n).map(drop),
+                None => Err(reader.error(ErrorKind::UnexpectedEnd)),
+            },
+            _ => {
+                for i in 0..len as usize {
+                    reader.check::<T>().map_err(|e| e.at_index(i))?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
+/// A fixed-size array, encoded as its elements without a length prefix.
+impl<T: Bebop, const N: usize> Bebop for [T; N] {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        // Read into the array in place, stopping at the first error.
+        let mut error = None;
+        let items: [Option<T>; N] = std::array::from_fn(|i| match error {
+            Some(_) => None,
+            None => reader
+                .read()
+                .map_err(|e: Error| error = Some(e.at_index(i)))
+                .ok(),
+        });
+        match error {
+            Some(error) => Err(error),
+            None => Ok(items.map(|item| item.unwrap_or_else(|| unreachable!()))),
+        }
+    }
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        for item in self {
+            writer.write(item)?;
+        }
+        Ok(())
+    }
+    const FIXED_SIZE: Option<usize> = match T::FIXED_SIZE {
+        Some(size) => Some(size * N),
+        None => None,
+    };
+    const ALWAYS_VALID: bool = T::ALWAYS_VALID;
+    fn encoded_size(&self) -> usize {
+        match Self::FIXED_SIZE {
+            Some(size) => size,
+            None => self.iter().map(Bebop::encoded_size).sum(),
+        }
+    }
+    fn encoded_sizes(&self, sizes: &mut Sizes) -> usize {
+        match Self::FIXED_SIZE {
+            Some(size) => size,
+            None => self.iter().map(|item| item.encoded_sizes(sizes)).sum(),
+        }
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        match Self::FIXED_SIZE {
+            Some(size) => reader.skip_raw(size),
+            None => {
+                for i in 0..N {
+                    reader.skip::<T>().map_err(|e| e.at_index(i))?;
+                }
+                Ok(())
+            }
+        }
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        if let (Some(size), true) = (Self::FIXED_SIZE, Self::ALWAYS_VALID) {
+            return reader.read_raw(size).map(drop);
+        }
+        for i in 0..N {
+            reader.check::<T>().map_err(|e| e.at_index(i))?;
+        }
+        Ok(())
+    }
+}
+
 impl<K: Bebop + Hash + Eq, V: Bebop> Bebop for HashMap<K, V> {
-    fn read_from(reader: &mut Reader) -> Result<Self, Error> {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        let len: u32 = reader.read()?;
+        let mut map = HashMap::with_capacity(reader.capacity_hint(len));
+        for i in 0..len as usize {
+            let key = reader.read().map_err(|e: Error| e.at_index(i))?;
+            let value = reader.read().map_err(|e: Error| e.at_index(i))?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_len(self.len())?;
+        for (key, value) in self {
+            writer.write(key)?;
+            writer.write(value)?;
+        }
+        Ok(())
+    }
+    fn encoded_size(&self) -> usize {
+        match (K::FIXED_SIZE, V::FIXED_SIZE) {
+            (Some(k), Some(v)) => 4 + (k + v) * self.len(),
+            _ => {
+                4 + self
+                    .iter()
+                    .map(|(key, value)| key.encoded_size() + value.encoded_size())
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn encoded_sizes(&self, sizes: &mut Sizes) -> usize {
+        match (K::FIXED_SIZE, V::FIXED_SIZE) {
+            (Some(k), Some(v)) => 4 + (k + v) * self.len(),
+            _ => {
+                4 + self
+                    .iter()
+                    .map(|(key, value)| key.encoded_sizes(sizes) + value.encoded_sizes(sizes))
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        let len: u32 = reader.read()?;
+        for i in 0..len as usize {
+            reader.skip::<K>().map_err(|e| e.at_index(i))?;
+            reader.skip::<V>().map_err(|e| e.at_index(i))?;
+        }
+        Ok(())
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        let len: u32 = reader.read()?;
+        for i in 0..len as usize {
+            reader.check::<K>().map_err(|e| e.at_index(i))?;
+            reader.check::<V>().map_err(|e| e.at_index(i))?;
+        }
+        Ok(())
+    }
+}
+
+/// Encoded in key order, so equal maps always have the same encoding.
+///
+/// In strict mode, keys that are duplicated or out of order are rejected.
+impl<K: Bebop + Ord, V: Bebop> Bebop for BTreeMap<K, V> {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
         let len: u32 = reader.read()?;
-        let mut map = HashMap::with_capacity(len as usize);
-        for _ in 0..len {
-            map.insert(reader.read()?, reader.read()?);
+        let mut map = BTreeMap::new();
+        for i in 0..len as usize {
+            let key = reader.read().map_err(|e: Error| e.at_index(i))?;
+            if reader.is_strict() && map.keys().next_back().is_some_and(|last| *last >= key) {
+                return Err(reader.error(ErrorKind::UnsortedElements).at_index(i));
+            }
+            let value = reader.read().map_err(|e: Error| e.at_index(i))?;
+            map.insert(key, value);
         }
         Ok(map)
     }
-    fn write_into(&self, writer: &mut Writer) {
-        writer.write(&u32::try_from(self.len()).unwrap());
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_len(self.len())?;
         for (key, value) in self {
-            writer.write(key);
-            writer.write(value);
+            writer.write(key)?;
+            writer.write(value)?;
+        }
+        Ok(())
+    }
+    fn encoded_size(&self) -> usize {
+        match (K::FIXED_SIZE, V::FIXED_SIZE) {
+            (Some(k), Some(v)) => 4 + (k + v) * self.len(),
+            _ => {
+                4 + self
+                    .iter()
+                    .map(|(key, value)| key.encoded_size() + value.encoded_size())
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn encoded_sizes(&self, sizes: &mut Sizes) -> usize {
+        match (K::FIXED_SIZE, V::FIXED_SIZE) {
+            (Some(k), Some(v)) => 4 + (k + v) * self.len(),
+            _ => {
+                4 + self
+                    .iter()
+                    .map(|(key, value)| key.encoded_sizes(sizes) + value.encoded_sizes(sizes))
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        let len: u32 = reader.read()?;
+        for i in 0..len as usize {
+            reader.skip::<K>().map_err(|e| e.at_index(i))?;
+            reader.skip::<V>().map_err(|e| e.at_index(i))?;
+        }
+        Ok(())
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        // Keys can only be compared once decoded.
+        if reader.is_strict() {
+            return Self::read_from(reader).map(drop);
         }
+        let len: u32 = reader.read()?;
+        for i in 0..len as usize {
+            reader.check::<K>().map_err(|e| e.at_index(i))?;
+            reader.check::<V>().map_err(|e| e.at_index(i))?;
+        }
+        Ok(())
+    }
+}
+
+/// Encoded as a sorted array.
+///
+/// In strict mode, elements that are duplicated or out of order are rejected.
+impl<T: Bebop + Ord> Bebop for BTreeSet<T> {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        let len: u32 = reader.read()?;
+        let mut set = BTreeSet::new();
+        for i in 0..len as usize {
+            let item = reader.read().map_err(|e: Error| e.at_index(i))?;
+            if reader.is_strict() && set.iter().next_back().is_some_and(|last| *last >= item) {
+                return Err(reader.error(ErrorKind::UnsortedElements).at_index(i));
+            }
+            set.insert(item);
+        }
+        Ok(set)
+    }
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write_len(self.len())?;
+        for item in self {
+            writer.write(item)?;
+        }
+        Ok(())
+    }
+    fn encoded_size(&self) -> usize {
+        match T::FIXED_SIZE {
+            Some(size) => 4 + size * self.len(),
+            None => 4 + self.iter().map(Bebop::encoded_size).sum::<usize>(),
+        }
+    }
+    fn encoded_sizes(&self, sizes: &mut Sizes) -> usize {
+        match T::FIXED_SIZE {
+            Some(size) => 4 + size * self.len(),
+            None => {
+                4 + self
+                    .iter()
+                    .map(|item| item.encoded_sizes(sizes))
+                    .sum::<usize>()
+            }
+        }
+    }
+    fn skip<R: Read>(reader: &mut Reader<R>) -> Result<(), Error> {
+        Vec::<T>::skip(reader)
+    }
+    fn check(reader: &mut Reader<&[u8]>) -> Result<(), Error> {
+        // Elements can only be compared once decoded.
+        if reader.is_strict() {
+            return Self::read_from(reader).map(drop);
+        }
+        Vec::<T>::check(reader)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::Bebop;
+    use crate::{Bebop, Date, ErrorKind, Guid, Writer};
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
 
     #[test]
     fn string() {
@@ -146,4 +638,93 @@ mod test {
         let s2 = String::decode(&msg).unwrap();
         assert_eq!(s, s2);
     }
+
+    #[test]
+    fn check_bytes() {
+        let bytes = vec![7u8; 1000].encode();
+        assert_eq!(Vec::<u8>::validate(&bytes), Ok(()));
+        let error = Vec::<u8>::validate(&bytes[..999]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEnd);
+        assert_eq!(
+            <[u16; 2]>::validate(&[1, 0, 2]).unwrap_err().kind(),
+            ErrorKind::UnexpectedEnd
+        );
+        // Not every byte is a valid bool.
+        let bools = [2, 0, 0, 0, 1, 2];
+        assert_eq!(Vec::<bool>::validate(&bools), Ok(()));
+        let mut reader = crate::Reader::new(&bools).strict();
+        assert_eq!(
+            reader.check::<Vec<bool>>().unwrap_err().kind(),
+            ErrorKind::InvalidBool
+        );
+    }
+
+    #[test]
+    fn bogus_length() {
+        // Would need 32 GiB if the length was trusted.
+        let bytes = [0xff, 0xff, 0xff, 0xff, 0];
+        let error = Vec::<u64>::decode(&bytes).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEnd);
+        let error = Vec::<u64>::decode_from(&bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEnd);
+        let error = HashMap::<u32, u64>::decode(&bytes).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEnd);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn length_overflow() {
+        let mut writer = Writer::new();
+        assert_eq!(writer.write_len(u32::MAX as usize), Ok(()));
+        assert_eq!(
+            writer.write_len(u32::MAX as usize + 1).unwrap_err().kind(),
+            ErrorKind::LengthOverflow
+        );
+        assert_eq!(writer.finish(), [0xff; 4]);
+    }
+
+    #[test]
+    fn encoded_size() {
+        let v = vec![1u16, 2, 3];
+        assert_eq!(v.encoded_size(), v.encode().len());
+        let v = vec![String::from("a"), String::from("bc")];
+        assert_eq!(v.encoded_size(), v.encode().len());
+        let mut m = HashMap::new();
+        m.insert(Guid::default(), vec![Date(1)]);
+        m.insert(Guid(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11), vec![]);
+        assert_eq!(m.encoded_size(), m.encode().len());
+        let mut m = HashMap::new();
+        m.insert(0u8, 1.5f64);
+        assert_eq!(m.encoded_size(), m.encode().len());
+    }
+
+    #[test]
+    fn btree() {
+        let a = BTreeMap::from([(String::from("b"), 2u8), (String::from("a"), 1)]);
+        let b = BTreeMap::from([(String::from("a"), 1u8), (String::from("b"), 2)]);
+        assert_eq!(a.encode(), b.encode());
+        assert_eq!(a.encode(), b"\x02\0\0\0\x01\0\0\0a\x01\x01\0\0\0b\x02");
+        assert_eq!(BTreeMap::decode(&a.encode()), Ok(a));
+
+        let set = BTreeSet::from([3u16, 1, 2]);
+        assert_eq!(set.encode(), vec![1u16, 2, 3].encode());
+        assert_eq!(BTreeSet::decode(&set.encode()), Ok(set.clone()));
+        assert_eq!(BTreeSet::decode_strict(&set.encode()), Ok(set));
+
+        for unsorted in [vec![1u16, 3, 2], vec![1, 1]] {
+            let bytes = unsorted.encode();
+            assert!(BTreeSet::<u16>::decode(&bytes).is_ok());
+            assert!(BTreeSet::<u16>::validate(&bytes).is_ok());
+            let error = BTreeSet::<u16>::decode_strict(&bytes).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::UnsortedElements);
+            assert_eq!(error.path(), None);
+            let mut reader = crate::Reader::new(&bytes).strict();
+            let error = reader.check::<BTreeSet<u16>>().unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::UnsortedElements);
+        }
+        let bytes = b"\x02\0\0\0\x01\0\0\0b\x02\x01\0\0\0a\x01";
+        assert!(BTreeMap::<String, u8>::decode(bytes).is_ok());
+        let error = BTreeMap::<String, u8>::decode_strict(bytes).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnsortedElements);
+    }
 }
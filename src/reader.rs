@@ -1,35 +1,253 @@
 use super::Bebop;
 use super::Error;
-use std::convert::TryInto;
+use super::ErrorKind;
+use std::cmp::min;
+use std::io::{self, Read};
 
 #[derive(Debug, Clone)]
-pub struct Reader<'a> {
-    bytes: &'a [u8],
+pub struct Reader<R> {
+    inner: R,
+    /// Number of bytes left in the message or union we're currently reading.
+    limit: usize,
+    /// The length of the input, if it is all in memory.
+    input_len: Option<usize>,
+    /// Number of bytes read so far.
+    offset: usize,
+    strict: bool,
 }
 
-impl<'a> Reader<'a> {
+impl<'a> Reader<&'a [u8]> {
     pub const fn new(bytes: &'a [u8]) -> Self {
-        Self { bytes }
+        Self {
+            inner: bytes,
+            limit: usize::MAX,
+            offset: 0,
+            strict: false,
+            input_len: Some(bytes.len()),
+        }
+    }
+
+    pub fn read_raw(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        self.check_limit(n)?;
+        if self.inner.len() < n {
+            return Err(self.error(ErrorKind::UnexpectedEnd));
+        }
+        let (read, rest) = self.inner.split_at(n);
+        self.inner = rest;
+        self.advance(n);
+        Ok(read)
+    }
+
+    pub fn bytes_left(&self) -> &'a [u8] {
+        &self.inner[..min(self.inner.len(), self.limit)]
+    }
+
+    /// Check that the next value is a well-formed `T` and skip over it.
+    pub fn check<T: Bebop>(&mut self) -> Result<(), Error> {
+        T::check(self)
+    }
+
+    /// Check field `field`, recording its name in any error.
+    pub fn check_field<T: Bebop>(&mut self, field: &'static str) -> Result<(), Error> {
+        T::check(self).map_err(|e| e.in_field(field))
+    }
+}
+
+impl<R> Reader<R> {
+    /// Create a reader that pulls its input from `inner` as it goes.
+    ///
+    /// Reads are small and frequent, so wrap `inner` in a
+    /// [`std::io::BufReader`] when it is expensive to read from.
+    pub const fn from_reader(inner: R) -> Self {
+        Self {
+            inner,
+            limit: usize::MAX,
+            offset: 0,
+            strict: false,
+            input_len: None,
+        }
+    }
+
+    /// Only accept the canonical encoding of every value.
+    ///
+    /// This rejects booleans other than 0 and 1, message fields, set
+    /// elements and map keys that are duplicated or out of order, and bytes
+    /// left over in messages and unions after their contents, which are
+    /// otherwise silently accepted. Only `BTreeSet`s and `BTreeMap`s can
+    /// tell whether their contents are in order.
+    pub const fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    pub const fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// The number of bytes read so far.
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Create an error at the current position.
+    pub fn error(&self, kind: ErrorKind) -> Error {
+        Error::from(kind).at_offset(self.offset)
+    }
+
+    /// How many of the `len` elements of a collection to allocate room for
+    /// up front.
+    ///
+    /// `len` comes from the input, so it is capped by the number of bytes
+    /// that are left, or by a small number if that is unknown.
+    pub fn capacity_hint(&self, len: u32) -> usize {
+        const MAX_STREAMING: usize = 1024;
+        match self.input_len {
+            Some(input_len) => min(len as usize, min(self.limit, input_len - self.offset)),
+            None => min(len as usize, MAX_STREAMING),
+        }
+    }
+
+    fn check_limit(&self, n: usize) -> Result<(), Error> {
+        if self.limit < n {
+            return Err(self.error(ErrorKind::UnexpectedEnd));
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.limit -= n;
+        self.offset += n;
     }
 
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Reader<R> {
     pub fn read<T: Bebop>(&mut self) -> Result<T, Error> {
         T::read_from(self)
     }
 
-    pub fn read_raw(&mut self, n: usize) -> Result<&'a [u8], Error> {
-        if self.bytes.len() < n {
-            return Err(Error::UnexpectedEnd);
+    pub fn skip<T: Bebop>(&mut self) -> Result<(), Error> {
+        T::skip(self)
+    }
+
+    /// Skip field `field`, recording its name in any error.
+    pub fn skip_field<T: Bebop>(&mut self, field: &'static str) -> Result<(), Error> {
+        T::skip(self).map_err(|e| e.in_field(field))
+    }
+
+    /// Read field `field`, recording its name in any error.
+    pub fn read_field<T: Bebop>(&mut self, field: &'static str) -> Result<T, Error> {
+        T::read_from(self).map_err(|e| e.in_field(field))
+    }
+
+    /// Read `definition` with `f`, recording its name in any error.
+    pub fn read_definition<T>(
+        &mut self,
+        definition: &'static str,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        f(self).map_err(|e| e.in_definition(definition))
+    }
+
+    pub fn read_raw_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        self.check_limit(N)?;
+        let mut bytes = [0; N];
+        if let Err(e) = self.inner.read_exact(&mut bytes) {
+            return Err(self.error(e.into()));
         }
-        let (read, rest) = self.bytes.split_at(n);
-        self.bytes = rest;
-        Ok(read)
+        self.advance(N);
+        Ok(bytes)
     }
 
-    pub fn read_raw_array<const N: usize>(&mut self) -> Result<&'a [u8; N], Error> {
-        Ok(self.read_raw(N)?.try_into().unwrap())
+    /// Read `n` bytes into a new `Vec`.
+    ///
+    /// The `Vec` grows as data arrives, so a bogus length from an untrusted
+    /// source does not cause a huge allocation up front.
+    pub fn read_raw_vec(&mut self, n: usize) -> Result<Vec<u8>, Error> {
+        self.check_limit(n)?;
+        let mut bytes = Vec::new();
+        match (&mut self.inner).take(n as u64).read_to_end(&mut bytes) {
+            Ok(len) if len == n => {}
+            Ok(_) => return Err(self.error(ErrorKind::UnexpectedEnd)),
+            Err(e) => return Err(self.error(e.into())),
+        }
+        self.advance(n);
+        Ok(bytes)
     }
 
-    pub fn bytes_left(&self) -> &'a [u8] {
-        self.bytes
+    /// Skip over `n` bytes.
+    pub fn skip_raw(&mut self, n: usize) -> Result<(), Error> {
+        self.check_limit(n)?;
+        match io::copy(&mut (&mut self.inner).take(n as u64), &mut io::sink()) {
+            Ok(len) if len == n as u64 => {}
+            Ok(_) => return Err(self.error(ErrorKind::UnexpectedEnd)),
+            Err(e) => return Err(self.error(e.into())),
+        }
+        self.advance(n);
+        Ok(())
+    }
+
+    /// In strict mode, fail with `kind` if the message or union that is
+    /// being read has bytes left.
+    pub fn check_end(&self, kind: ErrorKind) -> Result<(), Error> {
+        if self.strict && self.limit != 0 {
+            return Err(self.error(kind));
+        }
+        Ok(())
+    }
+
+    /// Read a length-prefixed message, calling `f` with the index of each
+    /// field for it to read the field's value.
+    ///
+    /// `f` returns `false` for an unknown field, whose size is unknown, so
+    /// the rest of the message is skipped.
+    pub fn read_message(
+        &mut self,
+        mut f: impl FnMut(&mut Self, u8) -> Result<bool, Error>,
+    ) -> Result<(), Error> {
+        let len: u32 = self.read()?;
+        self.read_nested(len as usize, |reader| {
+            let mut last_index = 0;
+            loop {
+                let index = reader.read::<u8>()?;
+                if index == 0 {
+                    return reader.check_end(ErrorKind::InvalidPadding);
+                }
+                if reader.strict {
+                    if index <= last_index {
+                        return Err(reader.error(ErrorKind::UnsortedFields));
+                    }
+                    last_index = index;
+                }
+                if !f(reader, index)? {
+                    return Ok(());
+                }
+            }
+        })
+    }
+
+    /// Read the `len` bytes of a length-prefixed message or union body with `f`.
+    ///
+    /// `f` cannot read past the end of the body, and whatever it leaves
+    /// unread (e.g. unknown message fields) is skipped afterwards.
+    pub fn read_nested<T>(
+        &mut self,
+        len: usize,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.check_limit(len)?;
+        let outer_limit = self.limit - len;
+        self.limit = len;
+        let value = f(self)?;
+        self.skip_raw(self.limit)?;
+        self.limit = outer_limit;
+        Ok(value)
     }
 }
@@ -1,36 +1,164 @@
 use super::Bebop;
+use super::Dispatch;
+use super::Error;
+use super::ErrorKind;
+use super::Opcode;
+use std::convert::TryFrom;
+use std::io::Write;
 
-#[derive(Debug, Clone)]
-pub struct Writer {
-    bytes: Vec<u8>,
+/// The sizes of the messages and unions in a value, collected by
+/// [`Bebop::encoded_sizes`].
+#[derive(Debug, Clone, Default)]
+pub struct Sizes(Vec<(usize, usize)>);
+
+impl Sizes {
+    /// Reserve a place for the size of message or union `value`, before
+    /// those of the values nested in it.
+    pub fn start<T>(&mut self, value: &T) -> usize {
+        self.0.push((value as *const T as usize, 0));
+        self.0.len() - 1
+    }
+
+    /// Record `size` in place `index`, as returned by [`start`](Sizes::start).
+    pub fn finish(&mut self, index: usize, size: usize) -> usize {
+        self.0[index].1 = size;
+        size
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Writer<W = Vec<u8>> {
+    inner: W,
+    /// The sizes of the messages and unions being written, in the order
+    /// they are written. See [`Writer::nested_size`].
+    sizes: Sizes,
+    /// The index in `sizes` of the next message or union to be written.
+    next_size: usize,
 }
 
 impl Writer {
     pub const fn new() -> Self {
-        Self { bytes: Vec::new() }
+        Self::from_writer(Vec::new())
     }
 
-    pub fn reserve(&mut self, n: usize) {
-        self.bytes.reserve(n);
+    pub fn with_capacity(n: usize) -> Self {
+        Self::from_writer(Vec::with_capacity(n))
     }
 
-    pub fn write<T: Bebop>(&mut self, value: &T) {
-        value.write_into(self)
+    /// Create a writer that appends to `bytes`, reusing its allocation.
+    pub const fn from_vec(bytes: Vec<u8>) -> Self {
+        Self::from_writer(bytes)
     }
 
-    pub fn write_raw(&mut self, bytes: &[u8]) {
-        self.bytes.extend_from_slice(bytes);
+    /// Remove everything written so far, but keep the allocated memory for
+    /// the next use.
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    pub fn reserve(&mut self, n: usize) {
+        self.inner.reserve(n);
     }
 
     pub fn bytes(&self) -> &[u8] {
-        &self.bytes
+        &self.inner
     }
 
     pub fn bytes_mut(&mut self) -> &mut [u8] {
-        &mut self.bytes
+        &mut self.inner
     }
 
     pub fn finish(self) -> Vec<u8> {
-        self.bytes
+        self.inner
+    }
+}
+
+impl<W> Writer<W> {
+    /// Create a writer that streams directly into `inner`.
+    ///
+    /// Nothing is buffered, so wrap `inner` in a [`std::io::BufWriter`] when
+    /// it is expensive to write to in small pieces.
+    pub const fn from_writer(inner: W) -> Self {
+        Self {
+            inner,
+            sizes: Sizes(Vec::new()),
+            next_size: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn write<T: Bebop>(&mut self, value: &T) -> Result<(), Error> {
+        value.write_into(self)
+    }
+
+    /// The encoded size of `value`, a message or union that is about to be
+    /// written.
+    ///
+    /// The outermost message or union computes the sizes of everything
+    /// nested in it in one pass, and the nested ones then take their size
+    /// in the order they are written. Computing each size separately would
+    /// make encoding quadratic in the nesting depth.
+    ///
+    /// Sizes are matched to values by address, so a type that does not
+    /// forward [`Bebop::encoded_sizes`] only costs another sizing pass.
+    #[doc(hidden)]
+    pub fn nested_size<T: Bebop>(&mut self, value: &T) -> usize {
+        let address = value as *const T as usize;
+        match self.sizes.0.get(self.next_size) {
+            Some(&(a, size)) if a == address => {
+                self.next_size += 1;
+                size
+            }
+            _ => {
+                self.sizes.0.clear();
+                self.next_size = 1;
+                value.encoded_sizes(&mut self.sizes)
+            }
+        }
+    }
+
+    pub fn write_raw(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        Ok(self.inner.write_all(bytes)?)
+    }
+
+    /// Write a length as a `u32`, or fail with [`ErrorKind::LengthOverflow`] if it does not fit.
+    pub fn write_len(&mut self, len: usize) -> Result<(), Error> {
+        match u32::try_from(len) {
+            Ok(len) => self.write(&len),
+            Err(_) => Err(ErrorKind::LengthOverflow.into()),
+        }
+    }
+
+    /// Write `value` as a [`Frame`](crate::Frame) with only a length prefix.
+    pub fn write_frame<T: Bebop>(&mut self, value: &T) -> Result<(), Error> {
+        self.write_len(value.encoded_size())?;
+        self.write(value)
+    }
+
+    /// Write `value` as a [`Frame`](crate::Frame) with a length prefix and its opcode.
+    pub fn write_opcode_frame<T: Bebop + Opcode>(&mut self, value: &T) -> Result<(), Error> {
+        self.write_len(value.encoded_size())?;
+        self.write(&T::OPCODE)?;
+        self.write(value)
+    }
+
+    /// Write `value` as a [`Frame`](crate::Frame) with a length prefix and its opcode.
+    pub fn write_dispatch_frame<D: Dispatch>(&mut self, value: &D) -> Result<(), Error> {
+        self.write_len(value.encoded_size())?;
+        self.write(&value.opcode())?;
+        value.write_into(self)
     }
 }
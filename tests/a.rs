@@ -1,4 +1,4 @@
-use bebop::{bebop, Bebop};
+use bebop::{bebop, Bebop, ErrorKind};
 
 bebop!("tests/a.bop");
 
@@ -23,4 +23,296 @@ fn media_message() {
 
     let data2 = MediaMessage::decode(&bytes).unwrap();
     assert_eq!(data, data2);
+
+    let error = MediaMessage::decode(&bytes[..32]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEnd);
+    assert_eq!(error.offset(), Some(32));
+    assert_eq!(error.path().unwrap(), "MediaMessage.data.fragment[2]");
+    assert_eq!(
+        error.to_string(),
+        "unexpected end of message in MediaMessage.data.fragment[2] at byte 32"
+    );
+}
+
+#[test]
+fn encode_to_io_write() {
+    let data = MediaMessage {
+        codec: Some(VideoCodec::H265),
+        data: Some(VideoData {
+            time: 2.5,
+            width: 1920,
+            height: 1080,
+            fragment: vec![4; 100],
+        }),
+    };
+
+    assert_eq!(data.encoded_size(), data.encode().len());
+
+    let mut file = std::io::Cursor::new(Vec::new());
+    data.encode_to(&mut file).unwrap();
+    assert_eq!(file.into_inner(), data.encode());
+
+    let mut full = [0u8; 16];
+    assert_eq!(
+        data.encode_to(&mut full[..]).unwrap_err().kind(),
+        ErrorKind::Io(std::io::ErrorKind::WriteZero)
+    );
+}
+
+#[test]
+fn decode_from_io_read() {
+    let data = MediaMessage {
+        codec: None,
+        data: Some(VideoData {
+            time: 0.5,
+            width: 640,
+            height: 480,
+            fragment: vec![7; 50],
+        }),
+    };
+    let mut bytes = data.encode();
+    bytes.extend_from_slice(b"next record");
+
+    let mut file = std::io::Cursor::new(&bytes);
+    assert_eq!(MediaMessage::decode_from(&mut file).unwrap(), data);
+    assert_eq!(file.position() as usize, data.encoded_size());
+
+    assert_eq!(
+        MediaMessage::decode_from(&bytes[..20]).unwrap_err().kind(),
+        ErrorKind::UnexpectedEnd
+    );
+}
+
+#[test]
+fn fixed_size() {
+    assert_eq!(VideoCodec::FIXED_SIZE, Some(4));
+    assert_eq!(VideoData::FIXED_SIZE, None);
+    assert_eq!(MediaMessage::FIXED_SIZE, None);
+
+    let codecs = vec![VideoCodec::H264, VideoCodec::H265];
+    assert_eq!(codecs.encoded_size(), 12);
+    let bytes = codecs.encode();
+    assert_eq!(bytes.len(), 12);
+    // `with_capacity` may allocate more than asked for.
+    assert!(bytes.capacity() >= 12);
+}
+
+#[test]
+fn encode_without_allocation() {
+    let data = MediaMessage {
+        codec: Some(VideoCodec::H264),
+        data: None,
+    };
+    let expected = data.encode();
+
+    let mut buffer = Vec::with_capacity(100);
+    buffer.push(0xff);
+    data.encode_into(&mut buffer);
+    assert_eq!(buffer[0], 0xff);
+    assert_eq!(buffer[1..], expected[..]);
+    assert_eq!(buffer.capacity(), 100);
+
+    let mut writer = bebop::Writer::from_vec(buffer);
+    writer.clear();
+    writer.write(&data).unwrap();
+    assert_eq!(writer.bytes(), expected);
+    writer.clear();
+    assert!(writer.bytes().is_empty());
+    assert_eq!(writer.finish().capacity(), 100);
+
+    let mut slice = [0u8; 32];
+    assert_eq!(data.encode_to_slice(&mut slice), Ok(expected.len()));
+    assert_eq!(slice[..expected.len()], expected[..]);
+    assert_eq!(
+        data.encode_to_slice(&mut slice[..expected.len() - 1])
+            .unwrap_err()
+            .kind(),
+        ErrorKind::BufferTooSmall
+    );
+}
+
+#[test]
+fn strict() {
+    let data = MediaMessage {
+        codec: Some(VideoCodec::H264),
+        data: None,
+    };
+    let bytes = data.encode();
+    assert_eq!(bytes, b"\x06\0\0\0\x01\0\0\0\0\0");
+    assert_eq!(MediaMessage::decode_strict(&bytes), Ok(data.clone()));
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(MediaMessage::decode(&trailing), Ok(data.clone()));
+    assert_eq!(
+        MediaMessage::decode_strict(&trailing).unwrap_err().kind(),
+        ErrorKind::TrailingBytes
+    );
+
+    let duplicate = b"\x0b\0\0\0\x01\0\0\0\0\x01\x01\0\0\0\0";
+    assert!(MediaMessage::decode(duplicate).is_ok());
+    assert_eq!(
+        MediaMessage::decode_strict(duplicate).unwrap_err().kind(),
+        ErrorKind::UnsortedFields
+    );
+
+    let padding = b"\x07\0\0\0\x01\0\0\0\0\0\0";
+    assert_eq!(MediaMessage::decode(padding), Ok(data));
+    assert_eq!(
+        MediaMessage::decode_strict(padding).unwrap_err().kind(),
+        ErrorKind::InvalidPadding
+    );
+
+    assert_eq!(bool::decode(b"\x02"), Ok(true));
+    assert_eq!(
+        bool::decode_strict(b"\x02").unwrap_err().kind(),
+        ErrorKind::InvalidBool
+    );
+}
+
+#[test]
+fn views() {
+    let data = MediaMessage {
+        codec: None,
+        data: Some(VideoData {
+            time: 1.0,
+            width: 100,
+            height: 300,
+            fragment: vec![1, 2, 3],
+        }),
+    };
+    let bytes = data.encode();
+
+    let view = MediaMessageView::new(&bytes).unwrap();
+    assert_eq!(view.get_codec(), Ok(None));
+    assert_eq!(view.get_data(), Ok(data.data.clone()));
+    assert_eq!(view.decode(), Ok(data));
+
+    let video = VideoDataView::new(&bytes[5..]).unwrap();
+    assert_eq!(video.bytes(), &bytes[5..bytes.len() - 1]);
+    assert_eq!(video.get_height(), Ok(300));
+    assert_eq!(video.get_fragment(), Ok(vec![1, 2, 3]));
+
+    let error = VideoDataView::new(&bytes[5..27]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEnd);
+    assert_eq!(error.path().unwrap(), "VideoData");
+    assert_eq!(
+        MediaMessageView::new(&bytes[..10]).unwrap_err().kind(),
+        ErrorKind::UnexpectedEnd
+    );
+
+    // Errors from fields have the same offsets as when decoding everything.
+    let stream = Stream {
+        resolution: None,
+        name: Some("ab".into()),
+        started: None,
+    };
+    let mut bytes = stream.encode();
+    let last = bytes.len() - 2;
+    bytes[last] = 0xff;
+    let error = StreamView::new(&bytes).unwrap().get_name().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidUtf8);
+    assert_eq!(error.offset(), Some(5));
+    assert_eq!(error.offset(), Stream::decode(&bytes).unwrap_err().offset());
+
+    // Fields can have the same names as the methods of views.
+    let blob = Blob {
+        bytes: 1,
+        decode: 2,
+    };
+    let bytes = blob.encode();
+    let view = BlobView::new(&bytes).unwrap();
+    assert_eq!(view.get_bytes(), Ok(1));
+    assert_eq!(view.bytes(), &bytes[..]);
+    let packet = Packet {
+        find: Some(3),
+        new: None,
+    };
+    let bytes = packet.encode();
+    let view = PacketView::new(&bytes).unwrap();
+    assert_eq!(view.get_find(), Ok(Some(3)));
+    assert_eq!(view.get_new(), Ok(None));
+}
+
+#[test]
+fn fixed_size_arrays() {
+    let checksum = Checksum {
+        sha256: [0xab; 32],
+        matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    };
+    assert_eq!(Checksum::FIXED_SIZE, Some(32 + 24));
+
+    let bytes = checksum.encode();
+    assert_eq!(bytes.len(), 32 + 24);
+    assert_eq!(bytes[..32], [0xab; 32]);
+    assert_eq!(bytes[32..36], 1.0f32.to_le_bytes());
+    assert_eq!(Checksum::decode(&bytes), Ok(checksum));
+
+    let error = Checksum::decode(&bytes[..50]).unwrap_err();
+    assert_eq!(error.path().unwrap(), "Checksum.matrix[1][1]");
+}
+
+#[test]
+fn derives() {
+    fn copy<T: Copy + Ord + std::hash::Hash>() {}
+    fn ord<T: Ord>() {}
+    fn partial_ord<T: PartialOrd>() {}
+    copy::<VideoCodec>();
+    copy::<Resolution>();
+    // `Middle` and `Late` are defined after they are used.
+    copy::<Middle>();
+    ord::<Early>();
+    // `date` is not `Hash`, and messages are never `Copy`.
+    ord::<Stream>();
+    // Floats are only `PartialOrd`.
+    partial_ord::<VideoData>();
+    partial_ord::<MediaMessage>();
+
+    let resolutions = std::collections::BTreeSet::from([
+        Resolution {
+            width: 1920,
+            height: 1080,
+        },
+        Resolution {
+            width: 1280,
+            height: 720,
+        },
+    ]);
+    assert_eq!(resolutions.iter().next().unwrap().width, 1280);
+}
+
+#[test]
+fn readonly() {
+    let version = Version::new(1, 2, "beta".into());
+    assert_eq!((version.get_major(), version.get_minor()), (1, 2));
+    assert_eq!(version.get_label(), "beta");
+    assert_eq!(Version::decode(&version.encode()), Ok(version));
+}
+
+#[test]
+fn constructors_and_builders() {
+    let resolution = Resolution::new(640, 480);
+    assert_eq!(resolution.width, 640);
+
+    let message = MediaMessage::builder().codec(VideoCodec::H265).build();
+    assert_eq!(message.codec, Some(VideoCodec::H265));
+    assert_eq!(message.data, None);
+
+    let data = VideoData::new(1.5, 640, 480, vec![1, 2]);
+    let mut message = message.with_data(data.clone());
+    assert_eq!(message.data.as_ref(), Some(&data));
+    message.clear_codec();
+    assert_eq!(message.codec, None);
+    message.set_codec(VideoCodec::H264);
+    assert_eq!(message.take_codec(), Some(VideoCodec::H264));
+    assert_eq!(message, MediaMessage::builder().data(data).build());
+}
+
+#[test]
+fn defaults() {
+    assert_eq!(VideoCodec::default(), VideoCodec::H264);
+    assert_eq!(Quality::default(), Quality::Medium);
+    assert_eq!(Resolution::default(), Resolution::new(0, 0));
+    assert_eq!(VideoData::default().fragment, Vec::<u8>::new());
+    assert_eq!(Checksum::default().sha256, [0; 32]);
 }
@@ -0,0 +1,102 @@
+use bebop::{bebop, Attribute, AttributeValue, Attributes, Bebop, Opcode};
+
+bebop!("tests/attributes.bop");
+
+#[test]
+fn attributes() {
+    assert_eq!(
+        User::ATTRIBUTES,
+        [
+            Attribute {
+                name: "table",
+                args: &[AttributeValue::String("users")],
+            },
+            Attribute {
+                name: "cached",
+                args: &[],
+            },
+            Attribute {
+                name: "index",
+                args: &[
+                    AttributeValue::Ident("name"),
+                    AttributeValue::String("by_name"),
+                ],
+            },
+        ]
+    );
+    assert_eq!(
+        User::FIELD_ATTRIBUTES[0],
+        (
+            "id",
+            &[
+                Attribute {
+                    name: "column",
+                    args: &[AttributeValue::String("user_id")],
+                },
+                Attribute {
+                    name: "key",
+                    args: &[],
+                },
+            ][..]
+        )
+    );
+    assert_eq!(
+        User::FIELD_ATTRIBUTES[1],
+        (
+            "name",
+            &[Attribute {
+                name: "length",
+                args: &[
+                    AttributeValue::Number(1),
+                    AttributeValue::Number(-0x10),
+                    AttributeValue::Ident("bytes"),
+                ],
+            }][..]
+        )
+    );
+
+    // Built-in attributes are not listed.
+    assert_eq!(Event::OPCODE, 0x10);
+    assert_eq!(Event::ATTRIBUTES.len(), 1);
+    assert_eq!(Event::ATTRIBUTES[0].name, "audited");
+    assert_eq!(
+        Event::FIELD_ATTRIBUTES,
+        [(
+            "name",
+            &[Attribute {
+                name: "since",
+                args: &[AttributeValue::Number(2)],
+            }][..]
+        )]
+    );
+
+    assert_eq!(Level::default(), Level::Medium);
+    assert!(Level::ATTRIBUTES.is_empty());
+    assert_eq!(Level::FIELD_ATTRIBUTES[0].0, "Medium");
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated() {
+    let legacy = LegacyUser::new(1, "old".into());
+    assert_eq!(LegacyUser::decode(&legacy.encode()), Ok(legacy.clone()));
+
+    // Deprecated message fields are not encoded...
+    let profile = Profile {
+        name: Some("a".into()),
+        age: Some(30),
+        legacy: Some(legacy),
+    };
+    let without_age = Profile {
+        age: None,
+        ..profile.clone()
+    };
+    assert_eq!(profile.encode(), without_age.encode());
+
+    // ...and are skipped when decoding.
+    let bytes = [6, 0, 0, 0, 2, 30, 0, 0, 0, 0];
+    assert_eq!(Profile::decode(&bytes), Ok(Profile::default()));
+
+    let account = Account::Old(Old { id: 2 });
+    assert_eq!(Account::decode(&account.encode()), Ok(account));
+}
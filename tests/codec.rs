@@ -0,0 +1,56 @@
+mod common;
+
+use bebop::{DispatchCodec, ErrorKind, FrameCodec};
+use common::*;
+use futures_util::{SinkExt, StreamExt};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+#[tokio::test]
+async fn frame_codec() {
+    let (client, server) = tokio::io::duplex(64);
+
+    let sender = tokio::spawn(async move {
+        let mut sink = FramedWrite::new(client, FrameCodec::new());
+        for i in 0..100 {
+            sink.send(chat(&i.to_string())).await.unwrap();
+        }
+    });
+
+    let mut stream = FramedRead::new(server, FrameCodec::<Chat>::new());
+    for i in 0..100 {
+        assert_eq!(stream.next().await.unwrap().unwrap(), chat(&i.to_string()));
+    }
+    assert!(stream.next().await.is_none());
+    sender.await.unwrap();
+}
+
+#[tokio::test]
+async fn dispatch_codec() {
+    let (client, server) = tokio::io::duplex(64);
+
+    let mut sink = FramedWrite::new(client, DispatchCodec::new());
+    sink.send(Record::Ping(Ping { id: 1 })).await.unwrap();
+    sink.send(Record::Chat(chat("hello"))).await.unwrap();
+    drop(sink);
+
+    let stream = FramedRead::new(server, DispatchCodec::<Record>::new());
+    let received: Vec<_> = stream.map(Result::unwrap).collect().await;
+    assert_eq!(
+        received,
+        [Record::Ping(Ping { id: 1 }), Record::Chat(chat("hello"))]
+    );
+}
+
+#[tokio::test]
+async fn max_frame_len() {
+    let (client, server) = tokio::io::duplex(64);
+
+    let mut sink = FramedWrite::new(client, FrameCodec::new());
+    sink.send(chat("a long message")).await.unwrap();
+
+    let mut stream = FramedRead::new(server, FrameCodec::<Chat>::new().max_frame_len(8));
+    assert_eq!(
+        stream.next().await.unwrap().unwrap_err().kind(),
+        ErrorKind::FrameTooLarge
+    );
+}
@@ -0,0 +1,13 @@
+//! The schema shared by the frame and codec tests.
+#![allow(dead_code)]
+
+use bebop::{bebop, Date};
+
+bebop!("tests/frame.bop", dispatch = Record);
+
+pub fn chat(text: &str) -> Chat {
+    Chat {
+        text: Some(text.into()),
+        sent: Some(Date(1234)),
+    }
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use bebop::{Bebop, Dispatch, Dispatcher, ErrorKind, FrameDecoder, Opcode, Reader, Writer};
+use common::*;
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+
+#[test]
+fn partial_frames() {
+    let mut writer = Writer::new();
+    writer.write_opcode_frame(&Ping { id: 1 }).unwrap();
+    writer.write_opcode_frame(&chat("hello")).unwrap();
+    let bytes = writer.finish();
+
+    let mut decoder = FrameDecoder::with_opcode();
+    let mut frames = Vec::new();
+    for byte in &bytes {
+        decoder.push(&[*byte]);
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            frames.push(frame);
+        }
+    }
+    assert!(decoder.buffered().is_empty());
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].opcode, Some(Ping::OPCODE));
+    assert_eq!(frames[0].decode::<Ping>().unwrap(), Ping { id: 1 });
+    assert_eq!(frames[1].opcode, Some(0x12345678));
+    assert_eq!(frames[1].decode::<Chat>().unwrap(), chat("hello"));
+}
+
+#[test]
+fn many_frames() {
+    let mut writer = Writer::new();
+    for id in 0..100_000 {
+        writer.write_frame(&Ping { id }).unwrap();
+    }
+    let bytes = writer.finish();
+
+    // Push everything but half a frame, take the frames out, then push the rest.
+    let mut decoder = FrameDecoder::new();
+    let (head, tail) = bytes.split_at(bytes.len() - 6);
+    decoder.push(head);
+    let mut ids = Vec::new();
+    while let Some(frame) = decoder.next_frame().unwrap() {
+        ids.push(frame.decode::<Ping>().unwrap().id);
+    }
+    assert_eq!(decoder.buffered(), &head[head.len() - 6..]);
+    decoder.push(tail);
+    while let Some(frame) = decoder.next_frame().unwrap() {
+        ids.push(frame.decode::<Ping>().unwrap().id);
+    }
+    assert!(decoder.buffered().is_empty());
+    assert!(ids.iter().copied().eq(0..100_000));
+}
+
+#[test]
+fn frame_too_large() {
+    let mut writer = Writer::new();
+    writer.write_frame(&chat("this is a bit too long")).unwrap();
+
+    let mut decoder = FrameDecoder::new().max_frame_len(16);
+    decoder.push(&writer.finish()[..4]);
+    assert_eq!(
+        decoder.next_frame().unwrap_err().kind(),
+        ErrorKind::FrameTooLarge
+    );
+}
+
+#[test]
+fn loopback() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let sender = std::thread::spawn(move || {
+        let mut writer = Writer::from_writer(TcpStream::connect(addr).unwrap());
+        for i in 0..100 {
+            writer.write_frame(&chat(&i.to_string())).unwrap();
+        }
+        writer.get_mut().flush().unwrap();
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    let mut decoder = FrameDecoder::new();
+    let mut received = Vec::new();
+    while let Some(frame) = decoder.read_frame(&stream).unwrap() {
+        assert_eq!(frame.opcode, None);
+        received.push(frame.decode::<Chat>().unwrap());
+    }
+    sender.join().unwrap();
+
+    let expected: Vec<Chat> = (0..100).map(|i| chat(&i.to_string())).collect();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn dispatch_enum() {
+    let mut writer = Writer::new();
+    writer
+        .write_dispatch_frame(&Record::from(chat("hi")))
+        .unwrap();
+    writer.write_opcode_frame(&Ping { id: 7 }).unwrap();
+
+    let mut decoder = FrameDecoder::with_opcode();
+    decoder.push(&writer.finish());
+    let first = decoder.next_frame().unwrap().unwrap();
+    let second = decoder.next_frame().unwrap().unwrap();
+    assert_eq!(first.dispatch(), Ok(Record::Chat(chat("hi"))));
+    assert_eq!(second.dispatch(), Ok(Record::Ping(Ping { id: 7 })));
+
+    assert_eq!(Record::Ping(Ping { id: 7 }).opcode(), Ping::OPCODE);
+    assert_eq!(
+        Record::decode(0, b"").unwrap_err().kind(),
+        ErrorKind::UnknownOpcode
+    );
+}
+
+#[test]
+fn dispatcher() {
+    let mut pings = Vec::new();
+    let mut chats = Vec::new();
+    let mut dispatcher = Dispatcher::new();
+    dispatcher
+        .on(|ping: Ping| pings.push(ping.id))
+        .on(|chat: Chat| chats.push(chat.text.unwrap()));
+
+    dispatcher
+        .dispatch(Ping::OPCODE, &Ping { id: 3 }.encode())
+        .unwrap();
+    dispatcher
+        .dispatch(Chat::OPCODE, &chat("x").encode())
+        .unwrap();
+    assert_eq!(
+        dispatcher.dispatch(1, b"").unwrap_err().kind(),
+        ErrorKind::UnknownOpcode
+    );
+    drop(dispatcher);
+
+    assert_eq!(pings, [3]);
+    assert_eq!(chats, ["x"]);
+}
+
+#[test]
+fn strict_union() {
+    let event = Event::Joined(Joined { name: "a".into() });
+    let bytes = event.encode();
+    assert_eq!(bytes, b"\x05\0\0\0\x01\x01\0\0\0a");
+    assert_eq!(Event::decode_strict(&bytes), Ok(event.clone()));
+
+    let long = b"\x06\0\0\0\x01\x01\0\0\0a\0";
+    assert_eq!(Event::decode(long), Ok(event));
+    assert_eq!(
+        Event::decode_strict(long).unwrap_err().kind(),
+        ErrorKind::UnionLengthMismatch
+    );
+}
+
+#[test]
+fn skip_and_validate() {
+    let mut writer = Writer::new();
+    writer.write(&Event::Left(Left { reason: None })).unwrap();
+    writer.write(&chat("skipped")).unwrap();
+    writer.write(&vec![Ping { id: 1 }, Ping { id: 2 }]).unwrap();
+    writer.write(&Ping { id: 3 }).unwrap();
+    let bytes = writer.finish();
+
+    let mut reader = Reader::new(&bytes);
+    reader.skip::<Event>().unwrap();
+    reader.skip::<Chat>().unwrap();
+    reader.skip::<Vec<Ping>>().unwrap();
+    assert_eq!(reader.read::<Ping>().unwrap(), Ping { id: 3 });
+    assert!(reader.bytes_left().is_empty());
+
+    let mut bytes = chat("hello").encode();
+    assert!(Chat::validate(&bytes).is_ok());
+    bytes[10] = 0xff;
+    assert!(Chat::skip(&mut Reader::new(&bytes)).is_ok());
+    let error = Chat::validate(&bytes).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidUtf8);
+    assert_eq!(error.path().unwrap(), "Chat.text");
+
+    let event = Event::Joined(Joined { name: "a".into() }).encode();
+    assert!(Event::validate(&event).is_ok());
+    assert_eq!(
+        Event::validate(&event[..event.len() - 1])
+            .unwrap_err()
+            .kind(),
+        ErrorKind::UnexpectedEnd
+    );
+}
@@ -0,0 +1,89 @@
+use bebop::Bebop;
+
+/// `common.bop` is imported by `audio.bop` and listed here, but only defined once.
+mod flat {
+    bebop::bebop!("tests/imports/audio.bop", "tests/imports/common.bop");
+}
+
+/// Both files define `Packet`, which is fine in separate modules.
+mod modules {
+    bebop::bebop!(
+        "tests/imports/audio.bop",
+        "tests/imports/video.bop",
+        modules,
+        dispatch = Packet,
+    );
+}
+
+/// `top.bop` uses `Header` from `common.bop` through `mid.bop`.
+mod transitive {
+    bebop::bebop!("tests/imports/top.bop", modules);
+}
+
+/// Both files define `Packet`, and `Wrapper` uses the one in its own file.
+mod shadowed {
+    bebop::bebop!(
+        "tests/imports/ints.bop",
+        "tests/imports/floats.bop",
+        modules
+    );
+}
+
+#[test]
+fn flat() {
+    let packet = flat::Packet {
+        header: flat::Header { version: 1 },
+        samples: vec![0.5],
+    };
+    assert_eq!(flat::Packet::decode(&packet.encode()), Ok(packet));
+}
+
+#[test]
+fn modules() {
+    let header = modules::common::Header { version: 2 };
+    let audio = modules::audio::Packet {
+        header,
+        samples: vec![0.5],
+    };
+    let video = modules::video::Packet {
+        header,
+        frame: vec![1, 2, 3],
+    };
+    assert_eq!(modules::audio::Packet::decode(&audio.encode()), Ok(audio));
+    assert_eq!(modules::video::Packet::decode(&video.encode()), Ok(video));
+}
+
+#[test]
+fn dispatch_across_modules() {
+    use bebop::Dispatch;
+    let packet = modules::Packet::from(modules::video::Packet {
+        header: modules::common::Header { version: 3 },
+        frame: vec![4],
+    });
+    assert!(matches!(packet, modules::Packet::VideoPacket(_)));
+    let bytes = packet.encode();
+    assert_eq!(modules::Packet::decode(packet.opcode(), &bytes), Ok(packet));
+}
+
+#[test]
+fn transitive_imports() {
+    let header = transitive::common::Header { version: 4 };
+    let top = transitive::top::Top {
+        middle: transitive::mid::Middle { header },
+        header,
+    };
+    assert_eq!(transitive::top::Top::decode(&top.encode()), Ok(top));
+}
+
+#[test]
+fn same_name_in_other_module() {
+    fn eq<T: Eq>() {}
+    eq::<shadowed::ints::Packet>();
+    let wrapper = shadowed::floats::Wrapper {
+        p: shadowed::floats::Packet { f: 0.5 },
+    };
+    assert_eq!(
+        shadowed::floats::Wrapper::decode(&wrapper.encode()),
+        Ok(wrapper)
+    );
+}
@@ -0,0 +1,53 @@
+use bebop::{bebop, Bebop, Guid};
+use std::collections::BTreeMap;
+
+mod deterministic {
+    bebop::bebop!("tests/maps.bop", deterministic);
+}
+
+mod btree {
+    bebop::bebop!("tests/maps.bop", types(map = std::collections::BTreeMap<_, _>));
+}
+
+bebop!("tests/maps.bop");
+
+/// Lots of entries, so that a `HashMap` would be unlikely to keep them in
+/// the order they were inserted in.
+fn counts(reversed: bool) -> Vec<(String, u32)> {
+    let mut counts = (0..100)
+        .map(|i| (format!("key{}", i), i))
+        .collect::<Vec<_>>();
+    if reversed {
+        counts.reverse();
+    }
+    counts
+}
+
+#[test]
+fn deterministic() {
+    let palette = |reversed| deterministic::Palette {
+        counts: counts(reversed).into_iter().collect(),
+        colors: BTreeMap::from([
+            (Guid::default(), vec![deterministic::Color::Red]),
+            (Guid::from([1; 16]), vec![]),
+        ]),
+    };
+    let bytes = palette(false).encode();
+    assert_eq!(palette(true).encode(), bytes);
+    assert_eq!(
+        deterministic::Palette::decode_strict(&bytes),
+        Ok(palette(false))
+    );
+
+    let palette = |reversed| btree::Palette {
+        counts: counts(reversed).into_iter().collect(),
+        colors: BTreeMap::new(),
+    };
+    let btree_bytes = palette(false).encode();
+    assert_eq!(palette(true).encode(), btree_bytes);
+
+    // The same maps as `HashMap`s have the same contents.
+    let palette = Palette::decode(&btree_bytes).unwrap();
+    assert_eq!(palette.counts.len(), 100);
+    assert_eq!(palette.counts["key7"], 7);
+}
@@ -0,0 +1,90 @@
+use bebop::{bebop, Bebop, Error, Reader, Writer};
+use std::cell::Cell;
+use std::io::{Read, Write};
+
+bebop!(
+    "tests/nesting.bop",
+    fields(Node.value = Counted, Leaf.value = Counted),
+);
+
+thread_local! {
+    /// How often `Counted::encoded_size` was called on this thread.
+    static SIZED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A `u32` that counts how often it is sized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Counted(u32);
+
+impl Bebop for Counted {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> Result<Self, Error> {
+        reader.read().map(Counted)
+    }
+    fn write_into<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
+        writer.write(&self.0)
+    }
+    fn encoded_size(&self) -> usize {
+        SIZED.with(|sized| sized.set(sized.get() + 1));
+        4
+    }
+}
+
+fn node(depth: u32) -> Node {
+    let mut node = Node {
+        children: None,
+        value: Some(Counted(0)),
+    };
+    for i in 1..depth {
+        node = Node {
+            children: Some(vec![node]),
+            value: Some(Counted(i)),
+        };
+    }
+    node
+}
+
+fn tree(depth: u32) -> Tree {
+    let mut tree = Tree::Leaf(Leaf { value: Counted(0) });
+    for _ in 1..depth {
+        tree = Tree::Branch(Branch {
+            children: Some(vec![tree]),
+        });
+    }
+    tree
+}
+
+#[test]
+fn round_trip() {
+    let node = node(10);
+    assert_eq!(Node::decode(&node.encode()), Ok(node));
+    let tree = tree(10);
+    assert_eq!(Tree::decode(&tree.encode()), Ok(tree));
+}
+
+#[test]
+fn deep_nesting() {
+    // Encoding recurses once per level, which needs more than the default
+    // stack of a test thread in debug builds.
+    let thread = std::thread::Builder::new().stack_size(64 << 20);
+    thread.spawn(encode_deep).unwrap().join().unwrap();
+}
+
+fn encode_deep() {
+    let depth = 4000;
+
+    // Every value is sized once to allocate the buffer, and once more while
+    // writing, however deep it is nested.
+    let node = node(depth);
+    SIZED.with(|sized| sized.set(0));
+    let bytes = node.encode();
+    assert_eq!(SIZED.with(Cell::get), 2 * depth as usize);
+    assert_eq!(bytes.len(), node.encoded_size());
+    assert_eq!(bytes[..4], (bytes.len() as u32 - 4).to_le_bytes());
+
+    let tree = tree(depth);
+    SIZED.with(|sized| sized.set(0));
+    let bytes = tree.encode();
+    assert_eq!(SIZED.with(Cell::get), 2);
+    assert_eq!(bytes.len(), tree.encoded_size());
+    assert_eq!(bytes[..4], (bytes.len() as u32 - 5).to_le_bytes());
+}
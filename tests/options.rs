@@ -0,0 +1,35 @@
+use bebop::{bebop, Bebop};
+use std::collections::HashSet;
+
+bebop!(
+    "tests/options.bop",
+    module = schema,
+    vis = pub(crate),
+    derive(Eq, Hash),
+    attrs(Id = #[derive(PartialOrd)]),
+);
+
+#[test]
+fn options() {
+    use schema::{Id, Kind, Named};
+    let user = Id {
+        kind: Kind::User,
+        value: 7,
+    };
+    let group = Id {
+        kind: Kind::Group,
+        value: 1,
+    };
+    assert!(user < group);
+
+    let ids = HashSet::from([user, group, user]);
+    assert_eq!(ids.len(), 2);
+
+    let named = Named {
+        id: Some(user),
+        name: Some("root".into()),
+    };
+    let named = HashSet::from([named]);
+    let named = named.iter().next().unwrap();
+    assert_eq!(Named::decode(&named.encode()).as_ref(), Ok(named));
+}
@@ -0,0 +1,40 @@
+use bebop::{bebop, Bebop};
+use bytes::Bytes;
+use indexmap::IndexMap;
+use smallvec::SmallVec;
+use std::sync::Arc;
+
+bebop!(
+    "tests/types.bop",
+    types(
+        string = Box<str>,
+        bytes = bytes::Bytes,
+        array = smallvec::SmallVec<[_; 4]>,
+        map = indexmap::IndexMap<_, _>,
+    ),
+    fields(Blob.label = std::sync::Arc<str>, Note.text = String),
+);
+
+#[test]
+fn type_mapping() {
+    let blob = Blob {
+        name: "blob".into(),
+        data: Bytes::from_static(b"\x01\x02\x03"),
+        shorts: SmallVec::from_slice(&[1, 2, 3]),
+        counts: IndexMap::from([("b".into(), 2), ("a".into(), 1)]),
+        label: Arc::from("label"),
+    };
+    let bytes = blob.encode();
+    assert_eq!(bytes.len(), blob.encoded_size());
+    let decoded = Blob::decode(&bytes).unwrap();
+    assert_eq!(decoded, blob);
+    // IndexMap keeps the encoded order.
+    let keys = decoded.counts.keys().map(|k| &**k).collect::<Vec<_>>();
+    assert_eq!(keys, ["b", "a"]);
+
+    let note = Note {
+        text: Some(String::from("hi")),
+        attachment: Some(Bytes::from_static(b"x")),
+    };
+    assert_eq!(Note::decode(&note.encode()), Ok(note));
+}